@@ -64,7 +64,8 @@ async fn main() -> Result<()> {
         args.redirect_uri.clone(),
         Tenant::Consumers,
     );
-    let url = auth.code_auth_url();
+    let request = auth.code_auth_request();
+    let url = request.url();
     eprintln!("Code auth url: {url}");
     if open::that(url.as_str()).is_err() {
         eprintln!("Cannot open browser, please open the url above manually.");
@@ -72,22 +73,25 @@ async fn main() -> Result<()> {
     eprintln!("Please login in browser, paste the redirected URL here and then press <Enter>");
 
     let code = loop {
-        const NEEDLE: &str = "nativeclient?code=";
-
         eprint!("Redirected URL: ");
         io::stdout().flush()?;
         let mut inp = String::new();
         io::stdin().read_line(&mut inp)?;
-        let inp = inp.trim();
 
-        match inp.find(NEEDLE) {
-            Some(pos) => break inp[pos + NEEDLE.len()..].to_owned(),
-            _ => eprintln!("Invalid!"),
+        match request.parse_redirect_url(inp.trim()) {
+            Ok(code) => break code,
+            Err(err) => eprintln!("Invalid: {err}"),
         }
     };
 
     eprintln!("Logining...");
-    let token = auth.login_with_code(&code, &ClientCredential::None).await?;
+    let token = auth
+        .login_with_code(
+            &code,
+            Some(request.code_verifier()),
+            &ClientCredential::None,
+        )
+        .await?;
     let refresh_token = token.refresh_token.expect("Missing refresh token");
 
     {
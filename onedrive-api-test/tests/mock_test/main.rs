@@ -0,0 +1,1205 @@
+//! Test request building and response handling against a local mock server
+//! standing in for Microsoft Graph. No network access or account is required.
+use futures_util::TryStreamExt as _;
+use onedrive_api::{option::*, resource::*, *};
+use reqwest::{StatusCode, Url};
+use serde_json::json;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+mod util;
+use util::*;
+
+#[tokio::test]
+async fn test_get_drive() {
+    let server =
+        MockServer::start(|_| Response::json(200, &json!({ "id": "some-drive-id" }))).await;
+    let onedrive = server.onedrive();
+    assert_eq!(onedrive.base_url(), &server.base_url());
+
+    let drive = onedrive.get_drive().await.unwrap();
+    assert_eq!(drive.id.unwrap().as_str(), "some-drive-id");
+
+    let drive = OneDrive::new("mock-token", DriveId("some-drive-id".to_owned()))
+        .with_base_url(server.base_url())
+        .get_drive_with_option(ObjectOption::new().select(&[DriveField::id]))
+        .await
+        .unwrap();
+    assert_eq!(drive.id.unwrap().as_str(), "some-drive-id");
+
+    let reqs = server.requests();
+    assert_eq!(reqs.len(), 2);
+    assert_eq!(reqs[0].method, "GET");
+    assert_eq!(reqs[0].target, "/v1.0/me/drive");
+    assert_eq!(reqs[0].header("authorization"), Some("Bearer mock-token"));
+    assert_eq!(reqs[1].target, "/v1.0/drives/some-drive-id?%24select=id");
+}
+
+#[tokio::test]
+async fn test_base_url_trailing_slash() {
+    let server = MockServer::start(|req| {
+        if req.target.ends_with("/$batch") {
+            let sub = &req.json()["requests"][0];
+            return Response::json(
+                200,
+                &json!({ "responses": [{ "id": sub["id"], "status": 200, "body": { "id": "some-id" } }] }),
+            );
+        }
+        Response::json(200, &json!({ "id": "some-drive-id" }))
+    })
+    .await;
+    let base_url = Url::parse(&format!("{}/", server.base_url())).unwrap();
+    let onedrive = OneDrive::new("mock-token", DriveLocation::me()).with_base_url(base_url);
+    assert_eq!(onedrive.base_url(), &server.base_url());
+
+    onedrive.get_drive().await.unwrap();
+    let mut batch = onedrive.batch();
+    let handle = batch.get_item(ItemLocation::root());
+    batch.send().await.take(&handle).unwrap();
+
+    let reqs = server.requests();
+    assert_eq!(reqs[0].target, "/v1.0/me/drive");
+    assert_eq!(reqs[1].target, "/v1.0/$batch");
+    assert_eq!(reqs[1].json()["requests"][0]["url"], "/me/drive/root");
+}
+
+#[tokio::test]
+async fn test_get_item() {
+    let server = MockServer::start(|req| {
+        if req.target.contains("not-exist") {
+            Response::error(404, "itemNotFound")
+                .header("request-id", "some-request-id")
+                .header(
+                    "client-request-id",
+                    req.header("client-request-id").unwrap_or("some-request-id"),
+                )
+                .header(
+                    "x-ms-ags-diagnostic",
+                    r#"{"ServerInfo":{"DataCenter":"West US"}}"#,
+                )
+                .header("Date", "Sat, 17 Oct 2026 00:00:00 GMT")
+        } else {
+            Response::json(200, &json!({ "id": "some-item-id", "name": "file name" }))
+        }
+    })
+    .await;
+    let onedrive = server.onedrive();
+
+    let item = onedrive
+        .get_item(ItemLocation::from_path("/dir/file name").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(item.id.unwrap().as_str(), "some-item-id");
+    assert_eq!(item.name.as_deref(), Some("file name"));
+
+    let err = onedrive
+        .get_item(&ItemId("not-exist".to_owned()))
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(err.error_response().unwrap().code, "itemNotFound");
+    assert_eq!(err.error_code(), Some(ErrorCode::ItemNotFound));
+    assert!(err.is_not_found());
+    let diag = err.diagnostics().unwrap();
+    assert_eq!(diag.request_id.as_deref(), Some("some-request-id"));
+    assert_eq!(diag.client_request_id.as_deref(), Some("some-request-id"));
+    assert!(diag.ags_diagnostic.as_deref().unwrap().contains("West US"));
+    assert_eq!(diag.date.as_deref(), Some("Sat, 17 Oct 2026 00:00:00 GMT"));
+
+    let err = onedrive
+        .get_item_with_option(
+            &ItemId("not-exist".to_owned()),
+            ObjectOption::new().client_request_id("my-request-id"),
+        )
+        .await
+        .unwrap_err();
+    let diag = err.diagnostics().unwrap();
+    assert_eq!(diag.client_request_id.as_deref(), Some("my-request-id"));
+
+    let reqs = server.requests();
+    assert_eq!(reqs[0].target, "/v1.0/me/drive/root:%2Fdir%2Ffile%20name:");
+    assert_eq!(reqs[1].target, "/v1.0/me/drive/items/not-exist");
+    assert_eq!(reqs[2].header("client-request-id"), Some("my-request-id"));
+}
+
+#[tokio::test]
+async fn test_client_request_id_generator() {
+    let server = MockServer::start(|req| {
+        let id = req.header("client-request-id").unwrap_or_default();
+        if req.target.ends_with("/not-exist") {
+            // Error response without a JSON body.
+            return Response::empty(500)
+                .header("request-id", "some-request-id")
+                .header("client-request-id", id)
+                .body("Internal error");
+        }
+        Response::json(200, &json!({ "id": "some-item-id" }))
+    })
+    .await;
+    let count = Arc::new(AtomicUsize::new(0));
+    let onedrive = server.onedrive().with_client_request_id_generator({
+        let count = count.clone();
+        move || format!("generated-{}", count.fetch_add(1, Ordering::SeqCst))
+    });
+
+    onedrive.get_item(ItemLocation::root()).await.unwrap();
+    onedrive
+        .get_item_with_option(
+            ItemLocation::root(),
+            ObjectOption::new().client_request_id("my-request-id"),
+        )
+        .await
+        .unwrap();
+    let err = onedrive
+        .get_item(&ItemId("not-exist".to_owned()))
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::INTERNAL_SERVER_ERROR));
+    let diag = err.diagnostics().unwrap();
+    assert_eq!(diag.request_id.as_deref(), Some("some-request-id"));
+    assert_eq!(diag.client_request_id.as_deref(), Some("generated-1"));
+
+    let reqs = server.requests();
+    let ids = reqs
+        .iter()
+        .map(|req| req.header("client-request-id").unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, ["generated-0", "my-request-id", "generated-1"]);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_copy_and_delete() {
+    let server = MockServer::start(|req| match &*req.method {
+        "POST" => Response::empty(202).header("Location", "http://127.0.0.1:1/monitor"),
+        "DELETE" => Response::empty(204),
+        _ => Response::error(400, "invalidRequest"),
+    })
+    .await;
+    let onedrive = server.onedrive();
+
+    let monitor = onedrive
+        .copy(
+            ItemLocation::from_path("/src").unwrap(),
+            ItemLocation::from_path("/dest dir").unwrap(),
+            FileName::new("copied").unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(monitor.monitor_url(), "http://127.0.0.1:1/monitor");
+
+    onedrive
+        .delete(ItemLocation::from_path("/src").unwrap())
+        .await
+        .unwrap();
+
+    let reqs = server.requests();
+    assert_eq!(reqs[0].target, "/v1.0/me/drive/root:%2Fsrc:/copy");
+    assert_eq!(
+        reqs[0].json(),
+        json!({
+            "parentReference": { "path": "/drive/root:%2Fdest%20dir:" },
+            "name": "copied",
+        }),
+    );
+    assert_eq!(reqs[1].method, "DELETE");
+    assert_eq!(reqs[1].target, "/v1.0/me/drive/root:%2Fsrc:");
+}
+
+/// A token provider whose first token is always rejected by the server.
+#[derive(Debug, Default)]
+struct RotatingTokenProvider {
+    refreshed: AtomicUsize,
+}
+
+impl TokenProvider for RotatingTokenProvider {
+    fn access_token(&self) -> Pin<Box<dyn Future<Output = Result<String>> + Send + '_>> {
+        let token = format!("token-{}", self.refreshed.load(Ordering::SeqCst));
+        Box::pin(async move { Ok(token) })
+    }
+
+    fn refresh_rejected_token<'a>(
+        &'a self,
+        _rejected_token: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<String>>> + Send + 'a>> {
+        let cnt = self.refreshed.fetch_add(1, Ordering::SeqCst) + 1;
+        Box::pin(async move { Ok(Some(format!("token-{cnt}"))) })
+    }
+}
+
+#[tokio::test]
+async fn test_token_provider_replay() {
+    let server = MockServer::start(|req| {
+        if req.header("authorization") == Some("Bearer token-0") {
+            Response::error(401, "InvalidAuthenticationToken")
+        } else {
+            Response::json(200, &json!({ "id": "some-drive-id" }))
+        }
+    })
+    .await;
+    let provider = Arc::new(RotatingTokenProvider::default());
+    let onedrive = OneDrive::new_with_token_provider(provider.clone(), DriveLocation::me())
+        .with_base_url(server.base_url());
+
+    onedrive.get_drive().await.unwrap();
+    assert_eq!(provider.refreshed.load(Ordering::SeqCst), 1);
+    let reqs = server.requests();
+    assert_eq!(reqs.len(), 2);
+    assert_eq!(reqs[1].header("authorization"), Some("Bearer token-1"));
+}
+
+#[tokio::test]
+async fn test_retry_policy() {
+    // Throttle the first 2 requests of each path.
+    let server = MockServer::start({
+        let counts = std::sync::Mutex::new(std::collections::HashMap::<String, usize>::new());
+        move |req| {
+            let mut counts = counts.lock().unwrap();
+            let cnt = counts.entry(req.target.clone()).or_default();
+            *cnt += 1;
+            match (*cnt, &*req.method) {
+                (1, _) => {
+                    Response::error(429, "activityLimitReached").header("Retry-After", "3600")
+                }
+                (2, _) => Response::error(503, "serviceNotAvailable"),
+                (_, "GET") => Response::json(200, &json!({ "id": "some-drive-id" })),
+                _ => Response::json(201, &json!({ "id": "some-item-id" })),
+            }
+        }
+    })
+    .await;
+    // `Retry-After` is capped by the max delay.
+    let policy = RetryPolicy::new()
+        .backoff(Duration::from_millis(1), Duration::from_millis(10))
+        .max_delay(Duration::from_millis(10));
+    let onedrive = server.onedrive().with_retry_policy(policy.clone());
+    assert_eq!(onedrive.retry_policy(), Some(&policy));
+
+    let drive = onedrive.get_drive().await.unwrap();
+    assert_eq!(drive.id.unwrap().as_str(), "some-drive-id");
+    assert_eq!(server.requests().len(), 3);
+
+    // Non-idempotent requests are not retried by default.
+    let err = onedrive
+        .create_folder(ItemLocation::root(), FileName::new("dir").unwrap())
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::TOO_MANY_REQUESTS));
+    assert_eq!(err.retry_after(), Some(Duration::from_secs(3600)));
+    assert!(err.is_retryable());
+    assert_eq!(server.requests().len(), 4);
+
+    // Override the policy per call.
+    let item = onedrive
+        .create_folder_with_option(
+            ItemLocation::root(),
+            FileName::new("dir").unwrap(),
+            DriveItemPutOption::new().retry_policy(policy.clone().retry_non_idempotent(true)),
+        )
+        .await
+        .unwrap();
+    assert_eq!(item.id.unwrap().as_str(), "some-item-id");
+    assert_eq!(server.requests().len(), 6);
+
+    // Give up after max attempts.
+    let err = onedrive
+        .get_item_with_option(
+            ItemLocation::root(),
+            ObjectOption::new().retry_policy(policy.max_attempts(2)),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::SERVICE_UNAVAILABLE));
+    assert_eq!(server.requests().len(), 8);
+}
+
+#[tokio::test]
+async fn test_retry_policy_body_failure() {
+    let server =
+        MockServer::start(|_| Response::json(200, &json!({ "id": "some-item-id" })).truncated())
+            .await;
+    let policy = RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(10));
+
+    // Failures while reading the response body are not retried.
+    let err = server
+        .onedrive()
+        .with_retry_policy(policy)
+        .get_item(ItemLocation::root())
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), None);
+    assert!(!err.is_retryable());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_rate_limiter() {
+    let server = MockServer::start(|req| {
+        if req.target.ends_with("/throttled") {
+            Response::error(429, "activityLimitReached").header("Retry-After", "1")
+        } else {
+            Response::json(200, &json!({ "id": "some-item-id" }))
+                .header("RateLimit-Limit", "1000")
+                .header("RateLimit-Remaining", "100")
+                .header("RateLimit-Reset", "10")
+        }
+    })
+    .await;
+    let limiter = RateLimiter::new(2);
+    limiter.set_requests_per_second(100.0);
+    limiter.set_burst(10);
+    let onedrive = server.onedrive().with_rate_limiter(limiter.clone());
+    let other = server.onedrive().with_rate_limiter(limiter.clone());
+    assert_eq!(onedrive.rate_limiter().unwrap().max_concurrency(), 2);
+
+    // Slow down on `RateLimit-*` headers.
+    onedrive.get_item(ItemLocation::root()).await.unwrap();
+    assert_eq!(limiter.current_requests_per_second(), Some(10.0));
+
+    // The pause on throttling is shared.
+    let err = onedrive
+        .get_item(&ItemId("throttled".to_owned()))
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::TOO_MANY_REQUESTS));
+    assert_eq!(limiter.current_requests_per_second(), Some(6.25));
+    let start = Instant::now();
+    other.get_item(ItemLocation::root()).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(900));
+}
+
+#[tokio::test]
+async fn test_batch() {
+    let server = MockServer::start(|req| {
+        let responses = req.json()["requests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|sub| {
+                let id = sub["id"].as_str().unwrap();
+                if sub["url"].as_str().unwrap().contains("not-exist") {
+                    return json!({
+                        "id": id,
+                        "status": 404,
+                        "body": { "error": { "code": "itemNotFound", "message": "Not found" } },
+                    });
+                }
+                match sub["method"].as_str().unwrap() {
+                    "DELETE" => json!({ "id": id, "status": 204 }),
+                    "PATCH" => json!({
+                        "id": id,
+                        "status": 200,
+                        "body": { "id": format!("item-{id}"), "name": sub["body"]["name"] },
+                    }),
+                    _ => json!({ "id": id, "status": 200, "body": { "id": format!("item-{id}") } }),
+                }
+            })
+            .collect::<Vec<_>>();
+        Response::json(200, &json!({ "responses": responses }))
+    })
+    .await;
+    let onedrive = server.onedrive();
+
+    let mut batch = onedrive.batch();
+    let got = batch.get_item(ItemLocation::from_path("/dir").unwrap());
+    let moved = batch.move_(
+        ItemLocation::from_path("/file").unwrap(),
+        ItemLocation::from_path("/dir").unwrap(),
+        Some(FileName::new("renamed").unwrap()),
+    );
+    batch.depends_on(&moved, &got);
+    let missing = batch.delete(&ItemId("not-exist".to_owned()));
+    let deleted = (3..25)
+        .map(|i| batch.delete(&ItemId(format!("item-{i}"))))
+        .collect::<Vec<_>>();
+    // A chain across the boundary of the first 20 operations.
+    for w in deleted[15..20].windows(2) {
+        batch.depends_on(&w[1], &w[0]);
+    }
+    assert_eq!(batch.len(), 25);
+
+    let mut resp = batch.send().await;
+    assert_eq!(resp.take(&got).unwrap().id.unwrap().as_str(), "item-0");
+    let moved_item = resp.take(&moved).unwrap();
+    assert_eq!(moved_item.name.as_deref(), Some("renamed"));
+    let err = resp.take(&missing).unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::NOT_FOUND));
+    assert!(err.is_not_found());
+    for handle in &deleted {
+        resp.take(handle).unwrap();
+    }
+    assert!(resp.take(&got).is_err());
+
+    let reqs = server.requests();
+    assert_eq!(reqs.len(), 2);
+    assert_eq!(reqs[0].method, "POST");
+    assert_eq!(reqs[0].target, "/v1.0/$batch");
+    let subs = reqs[0].json()["requests"].as_array().unwrap().clone();
+    assert_eq!(subs.len(), 18);
+    assert_eq!(
+        subs[1],
+        json!({
+            "id": "1",
+            "method": "PATCH",
+            "url": "/me/drive/root:%2Ffile:",
+            "headers": { "content-type": "application/json" },
+            "body": {
+                "parentReference": { "path": "/drive/root:%2Fdir:" },
+                "name": "renamed",
+                "@microsoft.graph.conflictBehavior": "fail",
+            },
+            "dependsOn": ["0"],
+        }),
+    );
+    let subs = reqs[1].json()["requests"].as_array().unwrap().clone();
+    assert_eq!(subs.len(), 7);
+    assert_eq!(subs[1]["dependsOn"], json!(["18"]));
+}
+
+#[tokio::test]
+async fn test_batch_failed_request() {
+    let failed = AtomicBool::new(false);
+    let server = MockServer::start(move |req| {
+        if !failed.swap(true, Ordering::SeqCst) {
+            return Response::error(400, "invalidRequest");
+        }
+        let responses = req.json()["requests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|sub| json!({ "id": sub["id"], "status": 200, "body": { "id": "some-id" } }))
+            .collect::<Vec<_>>();
+        Response::json(200, &json!({ "responses": responses }))
+    })
+    .await;
+    let onedrive = server.onedrive();
+
+    let mut batch = onedrive.batch();
+    let handles = (0..=BatchRequest::MAX_REQUESTS)
+        .map(|_| batch.get_item(ItemLocation::root()))
+        .collect::<Vec<_>>();
+    let mut resp = batch.send().await;
+    for handle in &handles[..BatchRequest::MAX_REQUESTS] {
+        let err = resp.take(handle).unwrap_err();
+        assert_eq!(err.status_code(), Some(StatusCode::BAD_REQUEST));
+    }
+    let item = resp.take(&handles[BatchRequest::MAX_REQUESTS]).unwrap();
+    assert_eq!(item.id.unwrap().as_str(), "some-id");
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_download() {
+    const CONTENT: &[u8] = b"some file content";
+
+    let server = MockServer::start(|req| {
+        if req.target.starts_with("/download") {
+            Response::empty(200).body(CONTENT)
+        } else if req.header("if-none-match") == Some("some-etag") {
+            Response::empty(304)
+        } else {
+            Response::empty(302).header("Location", "/download/file?tempauth=some-secret")
+        }
+    })
+    .await;
+    let onedrive = server.onedrive();
+    let item = ItemLocation::from_path("/file").unwrap();
+
+    let stream = onedrive.download(item).await.unwrap();
+    let content = stream
+        .map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await
+        .unwrap();
+    assert_eq!(content, CONTENT);
+
+    let mut buf = Vec::new();
+    let len = onedrive.download_to_writer(item, &mut buf).await.unwrap();
+    assert_eq!(len, CONTENT.len() as u64);
+    assert_eq!(buf, CONTENT);
+
+    let dir = std::env::temp_dir().join(format!("onedrive-api-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file");
+    let len = onedrive.download_to_file(item, &path).await.unwrap();
+    assert_eq!(len, CONTENT.len() as u64);
+    assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+    assert!(!dir.join("file.part").exists());
+
+    let option = || ObjectOption::new().if_none_match(&Tag("some-etag".to_owned()));
+    assert!(onedrive
+        .download_with_option(item, option())
+        .await
+        .unwrap()
+        .is_none());
+    std::fs::write(&path, "old content").unwrap();
+    let ret = onedrive
+        .download_to_file_with_option(item, option(), &path)
+        .await
+        .unwrap();
+    assert_eq!(ret, None);
+    assert_eq!(std::fs::read(&path).unwrap(), b"old content");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let reqs = server.requests();
+    assert_eq!(reqs.len(), 8);
+    assert_eq!(reqs[0].target, "/v1.0/me/drive/root:%2Ffile:/content");
+    assert_eq!(reqs[0].header("authorization"), Some("Bearer mock-token"));
+    assert_eq!(reqs[1].target, "/download/file?tempauth=some-secret");
+    assert_eq!(reqs[1].header("authorization"), None);
+    assert_eq!(reqs[6].header("if-none-match"), Some("some-etag"));
+}
+
+#[tokio::test]
+async fn test_download_converted() {
+    let server = MockServer::start(|req| {
+        if req.target.starts_with("/download") {
+            Response::empty(200).body("%PDF-1.7")
+        } else if req.target.ends_with("format=pdf") {
+            Response::empty(302).header("Location", "/download/file.pdf?tempauth=some-secret")
+        } else {
+            Response::error(406, "notSupported")
+        }
+    })
+    .await;
+    let onedrive = server.onedrive();
+    let item = ItemLocation::from_path("/report.docx").unwrap();
+
+    let stream = onedrive
+        .download_converted(item, ConvertFormat::Pdf)
+        .await
+        .unwrap();
+    let content = stream
+        .map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await
+        .unwrap();
+    assert_eq!(content, b"%PDF-1.7");
+
+    let dir = std::env::temp_dir().join(format!(
+        "onedrive-api-test-converted-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("report.pdf");
+    let len = onedrive
+        .download_converted_to_file(item, ConvertFormat::Pdf, &path)
+        .await
+        .unwrap();
+    assert_eq!(len, 8);
+    assert_eq!(std::fs::read(&path).unwrap(), b"%PDF-1.7");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let err = onedrive
+        .download_converted(item, ConvertFormat::Glb)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.status_code(), Some(StatusCode::NOT_ACCEPTABLE));
+    assert_eq!(err.error_code(), Some(ErrorCode::NotSupported));
+
+    let reqs = server.requests();
+    assert_eq!(
+        reqs[0].target,
+        "/v1.0/me/drive/root:%2Freport.docx:/content?format=pdf",
+    );
+    assert_eq!(
+        reqs[4].target,
+        "/v1.0/me/drive/root:%2Freport.docx:/content?format=glb",
+    );
+}
+
+#[tokio::test]
+async fn test_download_range() {
+    const CONTENT: &[u8] = b"0123456789abcdef";
+    const E_TAG: &str = "\"{SOME-GUID},1\"";
+
+    let server = MockServer::start(|req| {
+        if req.target.starts_with("/download") {
+            let range = req.header("range").unwrap();
+            let (start, end) = range
+                .strip_prefix("bytes=")
+                .unwrap()
+                .split_once('-')
+                .unwrap();
+            let start = start.parse::<usize>().unwrap();
+            let end = end.parse::<usize>().map_or(CONTENT.len(), |last| last + 1);
+            if start >= CONTENT.len() {
+                return Response::empty(416)
+                    .header("Content-Range", &format!("bytes */{}", CONTENT.len()));
+            }
+            Response::empty(206)
+                .header(
+                    "Content-Range",
+                    &format!("bytes {start}-{}/{}", end - 1, CONTENT.len()),
+                )
+                .header("ETag", E_TAG)
+                .body(&CONTENT[start..end])
+        } else if req.target.contains("/content") {
+            Response::empty(302).header("Location", "/download/file?tempauth=some-secret")
+        } else {
+            Response::json(200, &json!({ "eTag": E_TAG, "size": CONTENT.len() }))
+        }
+    })
+    .await;
+    let onedrive = server.onedrive();
+    let item = ItemLocation::from_path("/file").unwrap();
+    let e_tag = Tag(E_TAG.to_owned());
+
+    let download = onedrive.download_range(item, 2..6).await.unwrap();
+    assert_eq!(download.range(), 2..6);
+    assert_eq!(download.total_size(), Some(16));
+    assert_eq!(download.e_tag(), Some(&e_tag));
+    let mut buf = Vec::new();
+    assert_eq!(download.write_to(&mut buf).await.unwrap(), 4);
+    assert_eq!(buf, b"2345");
+
+    let download = onedrive.download_range(item, 10..).await.unwrap();
+    assert_eq!(download.range(), 10..16);
+
+    let err = onedrive.download_range(item, 16..).await.unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::RANGE_NOT_SATISFIABLE));
+
+    let dir = std::env::temp_dir().join(format!("onedrive-api-test-range-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file");
+    let part_path = dir.join("file.part");
+
+    // Resume from the partial file.
+    std::fs::write(&part_path, &CONTENT[..5]).unwrap();
+    let len = onedrive
+        .download_to_file_resumable(item, &e_tag, &path)
+        .await
+        .unwrap();
+    assert_eq!(len, 16);
+    assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+    assert!(!part_path.exists());
+
+    // The partial file is already complete.
+    std::fs::write(&part_path, CONTENT).unwrap();
+    let len = onedrive
+        .download_to_file_resumable(item, &e_tag, &path)
+        .await
+        .unwrap();
+    assert_eq!(len, 16);
+    assert!(!part_path.exists());
+
+    // The partial file is longer than the file.
+    std::fs::write(&part_path, [CONTENT, b"more"].concat()).unwrap();
+    let err = onedrive
+        .download_to_file_resumable(item, &e_tag, &path)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::RANGE_NOT_SATISFIABLE));
+    assert!(!part_path.exists());
+
+    // The file is modified.
+    std::fs::write(&part_path, &CONTENT[..5]).unwrap();
+    let err = onedrive
+        .download_to_file_resumable(item, &Tag("\"{SOME-GUID},0\"".to_owned()), &path)
+        .await
+        .unwrap_err();
+    assert!(err.is_conflict());
+    assert_eq!(err.error_code(), Some(ErrorCode::ResourceModified));
+    assert!(!part_path.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let reqs = server.requests();
+    assert_eq!(reqs[0].header("range"), Some("bytes=2-5"));
+    assert_eq!(reqs[1].target, "/download/file?tempauth=some-secret");
+    assert_eq!(reqs[1].header("range"), Some("bytes=2-5"));
+    assert_eq!(reqs[3].header("range"), Some("bytes=10-"));
+    assert_eq!(reqs[7].header("range"), Some("bytes=5-"));
+    assert_eq!(
+        reqs[10].target,
+        "/v1.0/me/drive/root:%2Ffile:?%24select=eTag%2Csize"
+    );
+}
+
+#[tokio::test]
+async fn test_segmented_download() {
+    let content = (0..100u8).collect::<Vec<_>>();
+    let url_count = Arc::new(AtomicUsize::new(0));
+    let segment_count = Arc::new(AtomicUsize::new(0));
+    let failed_once = Arc::new(AtomicBool::new(false));
+    let server = MockServer::start({
+        let content = content.clone();
+        let url_count = url_count.clone();
+        let segment_count = segment_count.clone();
+        let failed_once = failed_once.clone();
+        move |req| {
+            if req.target.ends_with("/content") {
+                let generation = url_count.fetch_add(1, Ordering::SeqCst) + 1;
+                let host = req.header("host").unwrap();
+                return Response::empty(302).header(
+                    "Location",
+                    &format!("http://{host}/download/file?generation={generation}"),
+                );
+            }
+            let count = segment_count.fetch_add(1, Ordering::SeqCst) + 1;
+            let (start, last) = req
+                .header("range")
+                .unwrap()
+                .strip_prefix("bytes=")
+                .unwrap()
+                .split_once('-')
+                .unwrap();
+            let (start, last) = (
+                start.parse::<usize>().unwrap(),
+                last.parse::<usize>().unwrap(),
+            );
+            // The first download URL expires after 3 segments, and the segment at 32 fails once.
+            if req.target.ends_with("generation=1") && count > 3 {
+                return Response::empty(403);
+            }
+            if start == 32 && !failed_once.swap(true, Ordering::SeqCst) {
+                return Response::empty(503);
+            }
+            Response::empty(206)
+                .header(
+                    "Content-Range",
+                    &format!("bytes {start}-{last}/{}", content.len()),
+                )
+                .header("ETag", "\"{SOME-GUID},1\"")
+                .body(&content[start..=last])
+        }
+    })
+    .await;
+    let onedrive = server.onedrive();
+
+    let dir = std::env::temp_dir().join(format!(
+        "onedrive-api-test-segmented-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file");
+
+    let err = onedrive
+        .segmented_download(ItemLocation::from_path("/file").unwrap(), 100)
+        .connections(1)
+        .segment_size(16)
+        .max_attempts(1)
+        .download_to_file(&path)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::SERVICE_UNAVAILABLE));
+    assert!(!path.exists());
+    assert!(!dir.join("file.part").exists());
+
+    url_count.store(0, Ordering::SeqCst);
+    segment_count.store(0, Ordering::SeqCst);
+    failed_once.store(false, Ordering::SeqCst);
+    let reqs_before = server.requests().len();
+    onedrive
+        .segmented_download(ItemLocation::from_path("/file").unwrap(), 100)
+        .connections(3)
+        .segment_size(16)
+        .download_to_file(&path)
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert!(!dir.join("file.part").exists());
+
+    let reqs = &server.requests()[reqs_before..];
+    // The download URL is re-fetched once after it expires.
+    assert_eq!(url_count.load(Ordering::SeqCst), 2);
+    assert!(reqs.iter().any(|req| req.target.ends_with("generation=2")));
+    // The failed segment is retried.
+    let segment_reqs = reqs
+        .iter()
+        .filter(|req| req.header("range") == Some("bytes=32-47"))
+        .count();
+    assert!(segment_reqs >= 2);
+    for req in reqs
+        .iter()
+        .filter(|req| req.target.starts_with("/download"))
+    {
+        assert_eq!(req.header("authorization"), None);
+    }
+
+    // The size differs from the one of the file.
+    let err = onedrive
+        .segmented_download(ItemLocation::from_path("/file").unwrap(), 90)
+        .segment_size(16)
+        .download_to_file(&path)
+        .await
+        .unwrap_err();
+    assert_eq!(err.error_code(), Some(ErrorCode::ResourceModified));
+    assert!(!dir.join("file.part").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_upload() {
+    let server = MockServer::start(|req| {
+        let host = req.header("host").unwrap();
+        match (&*req.method, &*req.target) {
+            ("PUT", target)
+                if target.ends_with("/content?%40microsoft.graph.conflictBehavior=fail") =>
+            {
+                Response::json(
+                    201,
+                    &json!({ "id": "small-item-id", "size": req.body.len() }),
+                )
+            }
+            ("POST", target) if target.ends_with("/createUploadSession") => Response::json(
+                200,
+                &json!({
+                    "uploadUrl": format!("http://{host}/upload/session"),
+                    "expirationDateTime": "2026-10-18T00:00:00Z",
+                    "nextExpectedRanges": ["0-"],
+                }),
+            ),
+            ("PUT", "/upload/session") => {
+                let range = req.header("content-range").unwrap();
+                let (last, total) = range.split_once('-').unwrap().1.split_once('/').unwrap();
+                let (last, total) = (last.parse::<u64>().unwrap(), total.parse::<u64>().unwrap());
+                if last + 1 == total {
+                    Response::json(201, &json!({ "id": "large-item-id", "size": total }))
+                } else {
+                    Response::json(
+                        202,
+                        &json!({ "nextExpectedRanges": [format!("{}-", last + 1)] }),
+                    )
+                }
+            }
+            ("DELETE", "/upload/session") => Response::empty(204),
+            _ => Response::error(400, "invalidRequest"),
+        }
+    })
+    .await;
+    let onedrive = server.onedrive();
+
+    let dir = std::env::temp_dir().join(format!("onedrive-api-test-upload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("small");
+    std::fs::write(&path, "hello").unwrap();
+    let item = onedrive
+        .upload_file(ItemLocation::from_path("/small").unwrap(), &path)
+        .await
+        .unwrap();
+    assert_eq!(item.id.unwrap().as_str(), "small-item-id");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let size = OneDrive::UPLOAD_PART_SIZE * 2 + 1000;
+    let data = (0..=u8::MAX).cycle().take(size).collect::<Vec<_>>();
+    let item = onedrive
+        .upload(
+            ItemLocation::from_path("/large").unwrap(),
+            &mut &data[..],
+            size as u64,
+        )
+        .await
+        .unwrap();
+    assert_eq!(item.id.unwrap().as_str(), "large-item-id");
+
+    // The reader ends early.
+    let err = onedrive
+        .upload(
+            ItemLocation::from_path("/large").unwrap(),
+            &mut &data[..OneDrive::UPLOAD_PART_SIZE],
+            size as u64,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), None);
+
+    let reqs = server.requests();
+    assert_eq!(reqs[0].body, b"hello");
+    assert_eq!(
+        reqs[1].target,
+        "/v1.0/me/drive/root:%2Flarge:/createUploadSession"
+    );
+    let parts = &reqs[2..5];
+    let ranges = parts
+        .iter()
+        .map(|req| req.header("content-range").unwrap())
+        .collect::<Vec<_>>();
+    let part = OneDrive::UPLOAD_PART_SIZE;
+    assert_eq!(
+        ranges,
+        [
+            format!("bytes 0-{}/{size}", part - 1),
+            format!("bytes {part}-{}/{size}", 2 * part - 1),
+            format!("bytes {}-{}/{size}", 2 * part, size - 1),
+        ],
+    );
+    assert_eq!(parts[1].body, data[part..2 * part]);
+    assert_eq!(part % UploadSession::PART_SIZE_ALIGNMENT, 0);
+    assert_eq!(reqs[5].method, "POST");
+    assert_eq!(reqs[6].method, "PUT");
+    assert_eq!(reqs[7].method, "DELETE");
+    assert_eq!(reqs.len(), 8);
+}
+
+#[tokio::test]
+async fn test_upload_resume() {
+    let part = OneDrive::UPLOAD_PART_SIZE;
+    let size = part + 1000;
+    let resume_at = part + 500;
+    let failures = Arc::new(AtomicUsize::new(0));
+    let server = MockServer::start({
+        let failures = failures.clone();
+        move |req| {
+            let host = req.header("host").unwrap();
+            match (&*req.method, &*req.target) {
+                ("POST", _) => Response::json(
+                    200,
+                    &json!({
+                        "uploadUrl": format!("http://{host}/upload/session"),
+                        "expirationDateTime": "2026-10-18T00:00:00Z",
+                        "nextExpectedRanges": ["0-"],
+                    }),
+                ),
+                ("GET", "/upload/session") => Response::json(
+                    200,
+                    &json!({
+                        "expirationDateTime": "2026-10-18T00:00:00Z",
+                        "nextExpectedRanges": [format!("{resume_at}-")],
+                    }),
+                ),
+                ("PUT", "/upload/session") => {
+                    let range = req.header("content-range").unwrap();
+                    if range.starts_with("bytes 0-") {
+                        Response::json(202, &json!({ "nextExpectedRanges": [format!("{part}-")] }))
+                    } else if failures.fetch_add(1, Ordering::SeqCst) == 0 {
+                        Response::error(503, "serviceNotAvailable")
+                    } else {
+                        Response::json(201, &json!({ "id": "large-item-id", "size": size }))
+                    }
+                }
+                _ => Response::error(400, "invalidRequest"),
+            }
+        }
+    })
+    .await;
+    let data = (0..=u8::MAX).cycle().take(size).collect::<Vec<_>>();
+    let policy = RetryPolicy::new()
+        .max_attempts(2)
+        .backoff(Duration::from_millis(1), Duration::from_millis(10));
+
+    let item = server
+        .onedrive()
+        .with_retry_policy(policy.clone())
+        .upload(
+            ItemLocation::from_path("/large").unwrap(),
+            &mut &data[..],
+            size as u64,
+        )
+        .await
+        .unwrap();
+    assert_eq!(item.id.unwrap().as_str(), "large-item-id");
+
+    let reqs = server.requests();
+    let methods = reqs.iter().map(|req| &*req.method).collect::<Vec<_>>();
+    // Parts are not replayed blindly, but resumed from the session.
+    assert_eq!(methods, ["POST", "PUT", "PUT", "GET", "PUT"]);
+    assert_eq!(
+        reqs[4].header("content-range").unwrap(),
+        format!("bytes {resume_at}-{}/{size}", size - 1),
+    );
+    assert_eq!(reqs[4].body, data[resume_at..]);
+
+    // Retryable failures keep the session, which is resumable.
+    failures.store(0, Ordering::SeqCst);
+    let err = server
+        .onedrive()
+        .with_retry_policy(policy.max_attempts(1))
+        .upload(
+            ItemLocation::from_path("/large").unwrap(),
+            &mut &data[..],
+            size as u64,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::SERVICE_UNAVAILABLE));
+    let reqs = server.requests();
+    assert_eq!(reqs.len(), 8);
+    assert!(reqs.iter().all(|req| req.method != "DELETE"));
+}
+
+#[tokio::test]
+async fn test_upload_resume_lost_response() {
+    let part = OneDrive::UPLOAD_PART_SIZE;
+    let size = part + 1000;
+    // The response of the first part is lost, or it is replayed and conflicts.
+    let first_part_resp = Arc::new(Mutex::new(Response::hang_up()));
+    let server = MockServer::start({
+        let first_part_resp = first_part_resp.clone();
+        move |req| {
+            let host = req.header("host").unwrap();
+            match (&*req.method, &*req.target) {
+                ("POST", _) => Response::json(
+                    200,
+                    &json!({
+                        "uploadUrl": format!("http://{host}/upload/session"),
+                        "expirationDateTime": "2026-10-18T00:00:00Z",
+                        "nextExpectedRanges": ["0-"],
+                    }),
+                ),
+                ("GET", "/upload/session") => Response::json(
+                    200,
+                    &json!({
+                        "expirationDateTime": "2026-10-18T00:00:00Z",
+                        "nextExpectedRanges": [format!("{part}-")],
+                    }),
+                ),
+                ("PUT", "/upload/session") => {
+                    if req.header("content-range").unwrap().starts_with("bytes 0-") {
+                        first_part_resp.lock().unwrap().clone()
+                    } else {
+                        Response::json(201, &json!({ "id": "large-item-id", "size": size }))
+                    }
+                }
+                _ => Response::error(400, "invalidRequest"),
+            }
+        }
+    })
+    .await;
+    let data = (0..=u8::MAX).cycle().take(size).collect::<Vec<_>>();
+    let onedrive = server.onedrive().with_retry_policy(
+        RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(10)),
+    );
+    for resp in [Response::hang_up(), Response::error(416, "invalidRange")] {
+        *first_part_resp.lock().unwrap() = resp;
+        let item = onedrive
+            .upload(
+                ItemLocation::from_path("/large").unwrap(),
+                &mut &data[..],
+                size as u64,
+            )
+            .await
+            .unwrap();
+        assert_eq!(item.id.unwrap().as_str(), "large-item-id");
+    }
+
+    let reqs = server.requests();
+    let methods = reqs.iter().map(|req| &*req.method).collect::<Vec<_>>();
+    assert_eq!(methods, ["POST", "PUT", "GET", "PUT"].repeat(2));
+    for req in [&reqs[3], &reqs[7]] {
+        assert_eq!(
+            req.header("content-range").unwrap(),
+            format!("bytes {part}-{}/{size}", size - 1),
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_login_on_behalf_of() {
+    let server = MockServer::start(|req| {
+        if req.form("assertion").as_deref() == Some("some-user-token") {
+            Response::json(
+                200,
+                &json!({
+                    "token_type": "Bearer",
+                    "scope": "https://graph.microsoft.com/Files.Read",
+                    "expires_in": 3600,
+                    "access_token": "some-access-token",
+                }),
+            )
+        } else {
+            Response::json(
+                400,
+                &json!({
+                    "error": "interaction_required",
+                    "error_description": "AADSTS50079: Multi-factor authentication required.",
+                    "error_codes": [50079],
+                    "suberror": "basic_action",
+                    "claims": r#"{"access_token":{"capolids":{"essential":true}}}"#,
+                }),
+            )
+        }
+    })
+    .await;
+    let auth = server.auth(ScopeSet::new().with(Scope::FILES_READ));
+    let credential = ClientCredential::Secret("some-secret".to_owned());
+
+    let token = auth
+        .login_on_behalf_of("some-user-token", &credential)
+        .await
+        .unwrap();
+    assert_eq!(token.access_token, "some-access-token");
+    assert!(token.missing_scopes(auth.scopes()).is_empty());
+
+    let err = auth
+        .login_on_behalf_of("some-mfa-user-token", &credential)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::BAD_REQUEST));
+    let resp = err.oauth2_error_response().unwrap();
+    assert_eq!(resp.error, "interaction_required");
+    assert_eq!(resp.suberror.as_deref(), Some("basic_action"));
+    assert!(resp.claims.as_deref().unwrap().contains("capolids"));
+
+    let req = &server.requests()[0];
+    assert_eq!(req.method, "POST");
+    assert_eq!(req.target, "/some-tenant/oauth2/v2.0/token");
+    assert_eq!(
+        req.form("grant_type").as_deref(),
+        Some("urn:ietf:params:oauth:grant-type:jwt-bearer"),
+    );
+    assert_eq!(
+        req.form("requested_token_use").as_deref(),
+        Some("on_behalf_of")
+    );
+    assert_eq!(req.form("scope").as_deref(), Some("Files.Read"));
+    assert_eq!(req.form("client_secret").as_deref(), Some("some-secret"));
+}
+
+#[tokio::test]
+async fn test_login_token_cache_failure() {
+    #[derive(Debug)]
+    struct ReadOnlyStorage;
+
+    impl TokenStorage for ReadOnlyStorage {
+        fn load(&self) -> Pin<Box<dyn Future<Output = Result<Option<String>>> + Send + '_>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn store<'a>(
+            &'a self,
+            _: &'a str,
+        ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+            Box::pin(async {
+                Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Read only").into())
+            })
+        }
+    }
+
+    let server = MockServer::start(|_| {
+        Response::json(
+            200,
+            &json!({
+                "token_type": "Bearer",
+                "scope": "https://graph.microsoft.com/Files.Read",
+                "expires_in": 3600,
+                "access_token": "some-access-token",
+                "refresh_token": "some-refresh-token",
+            }),
+        )
+    })
+    .await;
+    let cache = TokenCache::new(Arc::new(ReadOnlyStorage));
+    let auth = server
+        .auth(
+            ScopeSet::new()
+                .with(Scope::FILES_READ)
+                .with(Scope::OFFLINE_ACCESS),
+        )
+        .with_token_cache(cache.clone());
+
+    let token = auth
+        .login_with_refresh_token("some-refresh-token", &ClientCredential::None)
+        .await
+        .unwrap();
+    assert_eq!(token.access_token, "some-access-token");
+    assert!(cache.take_store_error().is_some());
+    assert!(cache.take_store_error().is_none());
+}
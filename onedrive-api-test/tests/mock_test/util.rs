@@ -0,0 +1,256 @@
+use onedrive_api::*;
+use reqwest::Url;
+use std::{
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// A request received by `MockServer`.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &**v)
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).expect("Request body is not JSON")
+    }
+
+    pub fn form(&self, key: &str) -> Option<String> {
+        let body = std::str::from_utf8(&self.body).expect("Request body is not UTF-8");
+        Url::parse(&format!("http://localhost/?{body}"))
+            .unwrap()
+            .query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    }
+}
+
+/// A response to be sent by `MockServer`.
+#[derive(Debug, Clone)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    hang_up: bool,
+    truncated: bool,
+}
+
+impl Response {
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            hang_up: false,
+            truncated: false,
+        }
+    }
+
+    /// Close the connection without responding, as if the response is lost.
+    pub fn hang_up() -> Self {
+        Self {
+            hang_up: true,
+            ..Self::empty(0)
+        }
+    }
+
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Self::empty(status)
+            .header("Content-Type", "application/json")
+            .body(value.to_string())
+    }
+
+    pub fn error(status: u16, code: &str) -> Self {
+        Self::json(
+            status,
+            &serde_json::json!({
+                "error": {
+                    "code": code,
+                    "message": format!("Mock error {code}"),
+                },
+            }),
+        )
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Close the connection after sending half of the body, as if it is reset.
+    pub fn truncated(mut self) -> Self {
+        self.truncated = true;
+        self
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+/// A minimal HTTP/1.1 server on the loopback interface to stand in for Microsoft Graph.
+///
+/// Every connection serves exactly one request and is closed afterwards.
+pub struct MockServer {
+    base_url: Url,
+    requests: Arc<Mutex<Vec<Request>>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1.0", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let task = tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let requests = requests.clone();
+                    let handler = handler.clone();
+                    tokio::spawn(async move {
+                        serve_connection(stream, &requests, &*handler).await;
+                    });
+                }
+            }
+        });
+        Self {
+            base_url: Url::parse(&base_url).unwrap(),
+            requests,
+            task,
+        }
+    }
+
+    pub fn base_url(&self) -> Url {
+        self.base_url.clone()
+    }
+
+    /// Get an `Auth` whose authority host is this server.
+    pub fn auth(&self, scopes: impl Into<ScopeSet>) -> Auth {
+        let mut host = self.base_url();
+        host.set_path("");
+        Auth::new(
+            "some-client-id",
+            scopes,
+            "http://localhost",
+            Tenant::Issuer("some-tenant".to_owned()),
+        )
+        .with_cloud(&Cloud::new(host.clone(), host))
+    }
+
+    /// Get a `OneDrive` with a fixed token pointing to this server.
+    pub fn onedrive(&self) -> OneDrive {
+        OneDrive::new("mock-token", DriveLocation::me()).with_base_url(self.base_url())
+    }
+
+    /// Get all requests received, in order.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve_connection(
+    mut stream: TcpStream,
+    requests: &Mutex<Vec<Request>>,
+    handler: &Handler,
+) {
+    let Some(req) = read_request(&mut stream).await else {
+        return;
+    };
+    let resp = handler(&req);
+    requests.lock().unwrap().push(req);
+    if resp.hang_up {
+        return;
+    }
+
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        resp.status,
+        resp.body.len(),
+    );
+    for (name, value) in &resp.headers {
+        write!(head, "{name}: {value}\r\n").unwrap();
+    }
+    head += "\r\n";
+    let _ = stream.write_all(head.as_bytes()).await;
+    let body_len = if resp.truncated {
+        resp.body.len() / 2
+    } else {
+        resp.body.len()
+    };
+    let _ = stream.write_all(&resp.body[..body_len]).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut buf = Vec::new();
+    let head_len = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let mut chunk = [0u8; 4096];
+        let len = stream.read(&mut chunk).await.ok()?;
+        if len == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..len]);
+    };
+
+    let head = String::from_utf8(buf[..head_len].to_vec()).ok()?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_owned();
+    let target = request_line.next()?.to_owned();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_owned(), v.trim().to_owned()))
+        .collect::<Vec<_>>();
+
+    let content_len = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .map_or(0, |(_, v)| v.parse::<usize>().unwrap());
+    let mut body = buf[head_len..].to_vec();
+    while body.len() < content_len {
+        let mut chunk = [0u8; 4096];
+        let len = stream.read(&mut chunk).await.ok()?;
+        if len == 0 {
+            return None;
+        }
+        body.extend_from_slice(&chunk[..len]);
+    }
+
+    Some(Request {
+        method,
+        target,
+        headers,
+        body,
+    })
+}
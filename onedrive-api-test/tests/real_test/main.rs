@@ -33,7 +33,7 @@ async fn test_get_drive() {
     let drive_id = drive1.id.as_ref().expect("drive1 has no id");
 
     // #2
-    let drive2 = OneDrive::new(onedrive.access_token(), drive_id.clone())
+    let drive2 = OneDrive::new(onedrive.access_token().unwrap(), drive_id.clone())
         .get_drive_with_option(ObjectOption::new().select(&[DriveField::id, DriveField::owner]))
         .await
         .expect("Cannot get drive #2");
@@ -44,7 +44,7 @@ async fn test_get_drive() {
     // #3
     assert_eq!(
         OneDrive::new(
-            onedrive.access_token(),
+            onedrive.access_token().unwrap(),
             DriveId(format!("{}_inva_lid", drive_id.as_str())),
         )
         .get_drive()
@@ -626,6 +626,7 @@ async fn test_auth_error() {
         let err = auth
             .login_with_code(
                 "M11111111-2222-3333-4444-555555555555",
+                None,
                 &ClientCredential::None,
             )
             .await
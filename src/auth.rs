@@ -1,15 +1,36 @@
-use std::fmt;
+use std::{borrow::Cow, fmt, time::Duration};
 
 use crate::{
     error::{Error, Result},
-    util::handle_oauth2_error_response,
+    resource::OAuth2ErrorResponse,
+    util::{handle_oauth2_error_response, Cloud},
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
 use url::Url;
 
+#[cfg(feature = "client-assertion")]
+mod client_assertion;
+mod loopback;
+mod scope;
+mod token_cache;
+mod token_provider;
+
+#[cfg(feature = "client-assertion")]
+pub use self::client_assertion::ClientAssertion;
+pub use self::{
+    loopback::LoopbackRedirectListener,
+    scope::{Scope, ScopeSet},
+    token_cache::{CachedToken, FileTokenStorage, MemoryTokenStorage, TokenCache, TokenStorage},
+    token_provider::{RefreshTokenProvider, TokenProvider},
+};
+
 /// A list of the Microsoft Graph permissions that you want the user to consent to.
 ///
+/// This is a convenience for common OneDrive usages. Use [`ScopeSet`] for other scopes.
+///
 /// # See also
 /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/permissions-reference#files-permissions)
 #[derive(Clone, Copy, Debug, Default)]
@@ -64,6 +85,16 @@ impl Permission {
             if self.offline_access { " offline_access" } else { "" },
         )
     }
+
+    /// The closest permission covered by `scopes`. Scopes beyond files are ignored.
+    fn from_scopes(scopes: &ScopeSet) -> Self {
+        let has = |scope: Scope| scopes.contains(&scope);
+        Self {
+            write: has(Scope::FILES_READ_WRITE) || has(Scope::FILES_READ_WRITE_ALL),
+            access_shared: has(Scope::FILES_READ_ALL) || has(Scope::FILES_READ_WRITE_ALL),
+            offline_access: scopes.offline_access(),
+        }
+    }
 }
 
 /// Control who can sign into the application.
@@ -114,21 +145,26 @@ impl Tenant {
 pub struct Auth {
     client: Client,
     client_id: String,
+    scopes: ScopeSet,
     permission: Permission,
     redirect_uri: String,
     tenant: Tenant,
+    cloud: Cloud,
+    token_cache: Option<TokenCache>,
 }
 
 impl Auth {
     /// Create an new instance for OAuth2 to Microsoft Graph
-    /// with specified client identifier and permission.
+    /// with specified client identifier and scopes.
+    ///
+    /// `scopes` can be a [`Permission`] or a [`ScopeSet`].
     pub fn new(
         client_id: impl Into<String>,
-        permission: Permission,
+        scopes: impl Into<ScopeSet>,
         redirect_uri: impl Into<String>,
         tenant: Tenant,
     ) -> Self {
-        Self::new_with_client(Client::new(), client_id, permission, redirect_uri, tenant)
+        Self::new_with_client(Client::new(), client_id, scopes, redirect_uri, tenant)
     }
 
     /// Same as [`Auth::new`][auth_new] but with custom `reqwest::Client`.
@@ -137,19 +173,54 @@ impl Auth {
     pub fn new_with_client(
         client: Client,
         client_id: impl Into<String>,
-        permission: Permission,
+        scopes: impl Into<ScopeSet>,
         redirect_uri: impl Into<String>,
         tenant: Tenant,
     ) -> Self {
+        let scopes = scopes.into();
         Self {
             client,
             client_id: client_id.into(),
-            permission,
+            permission: Permission::from_scopes(&scopes),
+            scopes,
             redirect_uri: redirect_uri.into(),
             tenant,
+            cloud: Cloud::default(),
+            token_cache: None,
         }
     }
 
+    /// Set the cloud environment, which determines the authority host and the Microsoft Graph
+    /// host for [`login_with_client_credentials`][login_with_client_credentials].
+    ///
+    /// Default to be [`Cloud::global`].
+    ///
+    /// [login_with_client_credentials]: #method.login_with_client_credentials
+    #[must_use]
+    pub fn with_cloud(mut self, cloud: &Cloud) -> Self {
+        self.cloud = cloud.clone();
+        self
+    }
+
+    /// Persist tokens returned by user logins into `token_cache`.
+    ///
+    /// Successful responses of [`login_with_code`][login_with_code],
+    /// [`login_with_refresh_token`][login_with_refresh_token] and
+    /// [`login_with_device_code`][login_with_device_code] are stored automatically, so that
+    /// rotated refresh tokens are never lost.
+    ///
+    /// Failures of storing do not fail the login, since the token is already issued. Check
+    /// them by [`TokenCache::take_store_error`].
+    ///
+    /// [login_with_code]: #method.login_with_code
+    /// [login_with_refresh_token]: #method.login_with_refresh_token
+    /// [login_with_device_code]: #method.login_with_device_code
+    #[must_use]
+    pub fn with_token_cache(mut self, token_cache: TokenCache) -> Self {
+        self.token_cache = Some(token_cache);
+        self
+    }
+
     /// Get the `client` used to create this instance.
     #[must_use]
     pub fn client(&self) -> &Client {
@@ -162,8 +233,17 @@ impl Auth {
         &self.client_id
     }
 
+    /// Get the `scopes` used to create this instance.
+    #[must_use]
+    pub fn scopes(&self) -> &ScopeSet {
+        &self.scopes
+    }
+
     /// Get the `permission` used to create this instance.
+    ///
+    /// If this instance is created with a [`ScopeSet`], this is the closest permission it covers.
     #[must_use]
+    #[deprecated(note = "use `Auth::scopes` instead")]
     pub fn permission(&self) -> &Permission {
         &self.permission
     }
@@ -180,16 +260,26 @@ impl Auth {
         &self.tenant
     }
 
+    /// Get the cloud environment set by [`with_cloud`][with_cloud].
+    ///
+    /// [with_cloud]: #method.with_cloud
+    #[must_use]
+    pub fn cloud(&self) -> &Cloud {
+        &self.cloud
+    }
+
+    /// Get the token cache set by [`with_token_cache`][with_token_cache], if any.
+    ///
+    /// [with_token_cache]: #method.with_token_cache
+    #[must_use]
+    pub fn token_cache(&self) -> Option<&TokenCache> {
+        self.token_cache.as_ref()
+    }
+
     #[must_use]
     fn endpoint_url(&self, endpoint: &str) -> Url {
-        let mut url = Url::parse("https://login.microsoftonline.com").unwrap();
-        url.path_segments_mut().unwrap().extend([
-            self.tenant.to_issuer(),
-            "oauth2",
-            "v2.0",
-            endpoint,
-        ]);
-        url
+        self.cloud
+            .authority_url([self.tenant.to_issuer(), "oauth2", "v2.0", endpoint])
     }
 
     /// Get the URL for web browser for code flow.
@@ -201,12 +291,45 @@ impl Auth {
         let mut url = self.endpoint_url("authorize");
         url.query_pairs_mut()
             .append_pair("client_id", &self.client_id)
-            .append_pair("scope", &self.permission.to_scope_string())
+            .append_pair("scope", &self.scopes.to_scope_string())
             .append_pair("redirect_uri", &self.redirect_uri)
             .append_pair("response_type", "code");
         url
     }
 
+    /// Get the URL for web browser for code flow with PKCE and `state`, along with a new random
+    /// code verifier and state.
+    ///
+    /// The returned request should be kept locally. After login, the redirected URL should be
+    /// checked by [`CodeAuthRequest::parse_redirect_url`] to get the code, which is then passed to
+    /// [`login_with_code`][login_with_code] with the code verifier.
+    /// This is recommended over [`code_auth_url`][code_auth_url], especially for public
+    /// native clients.
+    ///
+    /// # Panics
+    /// Panic if the system random number generator fails.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-auth-code-flow#request-an-authorization-code)
+    ///
+    /// [login_with_code]: #method.login_with_code
+    /// [code_auth_url]: #method.code_auth_url
+    #[must_use]
+    pub fn code_auth_request(&self) -> CodeAuthRequest {
+        let code_verifier = PkceCodeVerifier::new_random();
+        let state = random_token::<16>();
+        let mut url = self.code_auth_url();
+        url.query_pairs_mut()
+            .append_pair("code_challenge", &code_verifier.code_challenge())
+            .append_pair("code_challenge_method", "S256")
+            .append_pair("state", &state);
+        CodeAuthRequest {
+            url,
+            code_verifier,
+            state,
+        }
+    }
+
     async fn request_token<'a>(
         &self,
         require_refresh: bool,
@@ -226,27 +349,49 @@ impl Auth {
         Ok(token_resp)
     }
 
+    /// Store `token_resp` into the token cache, if any.
+    ///
+    /// Failures of storing do not fail the login. They are kept in the cache, see
+    /// [`TokenCache::take_store_error`].
+    async fn cache_token(&self, token_resp: TokenResponse) -> TokenResponse {
+        if let Some(cache) = &self.token_cache {
+            cache.store_or_keep_error(&token_resp).await;
+        }
+        token_resp
+    }
+
     /// Login using a code.
     ///
+    /// `code_verifier` must be the one from [`CodeAuthRequest::code_verifier`] if the code is
+    /// requested via [`code_auth_request`][code_auth_request], or `None` if it is requested via
+    /// [`code_auth_url`][code_auth_url].
+    ///
     /// # See also
     /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/auth-v2-user?view=graph-rest-1.0#3-get-a-token)
+    ///
+    /// [code_auth_request]: #method.code_auth_request
+    /// [code_auth_url]: #method.code_auth_url
     pub async fn login_with_code(
         &self,
         code: &str,
+        code_verifier: Option<&PkceCodeVerifier>,
         client_credential: &ClientCredential,
     ) -> Result<TokenResponse> {
-        self.request_token(
-            self.permission.offline_access,
-            [
-                ("client_id", &self.client_id as &str),
-                ("code", code),
-                ("grant_type", "authorization_code"),
-                ("redirect_uri", &self.redirect_uri),
-            ]
-            .into_iter()
-            .chain(client_credential.params()),
-        )
-        .await
+        let token_resp = self
+            .request_token(
+                self.scopes.offline_access(),
+                [
+                    ("client_id", &self.client_id as &str),
+                    ("code", code),
+                    ("grant_type", "authorization_code"),
+                    ("redirect_uri", &self.redirect_uri),
+                ]
+                .into_iter()
+                .chain(code_verifier.map(|v| ("code_verifier", v.as_str())))
+                .chain(client_credential.resolve().params()),
+            )
+            .await?;
+        Ok(self.cache_token(token_resp).await)
     }
 
     /// Login using a refresh token.
@@ -256,7 +401,7 @@ impl Auth {
     ///
     /// # Panics
     /// Panic if the current [`Auth`][auth] is created with no
-    /// [`offline_access`][offline_access] permission.
+    /// [`offline_access`][offline_access] permission, or [`Scope::OFFLINE_ACCESS`] scope.
     ///
     /// # See also
     /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/auth-v2-user?view=graph-rest-1.0#5-use-the-refresh-token-to-get-a-new-access-token)
@@ -270,30 +415,368 @@ impl Auth {
         client_credential: &ClientCredential,
     ) -> Result<TokenResponse> {
         assert!(
-            self.permission.offline_access,
-            "Refresh token requires offline_access permission."
+            self.scopes.offline_access(),
+            "Refresh token requires offline_access scope."
+        );
+
+        let token_resp = self
+            .request_token(
+                true,
+                [
+                    ("client_id", &self.client_id as &str),
+                    ("grant_type", "refresh_token"),
+                    ("redirect_uri", &self.redirect_uri),
+                    ("refresh_token", refresh_token),
+                ]
+                .into_iter()
+                .chain(client_credential.resolve().params()),
+            )
+            .await?;
+        Ok(self.cache_token(token_resp).await)
+    }
+
+    /// Login as the application itself without a signed-in user, using client credentials.
+    ///
+    /// This requests the `.default` scope of Microsoft Graph in the [`Cloud`] of this [`Auth`],
+    /// eg. `https://graph.microsoft.com/.default`, that is, all application permissions granted
+    /// to the application by an administrator. The scopes of this [`Auth`] are ignored. The
+    /// returned [`TokenResponse`] never contains a [`refresh_token`][refresh_token], just login
+    /// again when the access token expires.
+    ///
+    /// Since there is no user, [`DriveLocation::me`][me] is not available for the access token.
+    /// Use other constructors of [`DriveLocation`][drive_location] instead.
+    ///
+    /// # Panics
+    /// Panic if `client_credential` is [`ClientCredential::None`], or the current [`Auth`] is
+    /// not created with [`Tenant::Issuer`]. Both are required by the client credentials flow.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-client-creds-grant-flow#get-a-token)
+    ///
+    /// [refresh_token]: ./struct.TokenResponse.html#structfield.refresh_token
+    /// [me]: ./struct.DriveLocation.html#method.me
+    /// [drive_location]: ./struct.DriveLocation.html
+    pub async fn login_with_client_credentials(
+        &self,
+        client_credential: &ClientCredential,
+    ) -> Result<TokenResponse> {
+        assert!(
+            *client_credential != ClientCredential::None,
+            "Client credentials flow requires a client secret or assertion.",
+        );
+        assert!(
+            matches!(self.tenant, Tenant::Issuer(_)),
+            "Client credentials flow requires a tenant identifier.",
+        );
+
+        let scope = self.cloud.graph_default_scope();
+        self.request_token(
+            false,
+            [
+                ("client_id", &self.client_id as &str),
+                ("grant_type", "client_credentials"),
+                ("scope", &scope),
+            ]
+            .into_iter()
+            .chain(client_credential.resolve().params()),
+        )
+        .await
+    }
+
+    /// Exchange an access token sent to a middle-tier API for a token to call Microsoft Graph
+    /// as the same user, using the on-behalf-of (OBO) flow.
+    ///
+    /// `assertion` is the access token received by the API, whose audience is the API itself.
+    /// The scopes of this [`Auth`] are requested for the new token.
+    ///
+    /// The returned token is specific to the user of `assertion`, so it is never stored into the
+    /// [`TokenCache`] set by [`with_token_cache`][with_token_cache].
+    ///
+    /// # Panics
+    /// Panic if `client_credential` is [`ClientCredential::None`], which is required by the
+    /// on-behalf-of flow.
+    ///
+    /// # Errors
+    /// If the user needs to interact, eg. consent to new scopes or satisfy Conditional Access
+    /// policies, an OAuth2 error `interaction_required` is returned, which can be inspected via
+    /// [`Error::oauth2_error_response`]. The claims challenge in
+    /// [`OAuth2ErrorResponse::claims`][claims] should be sent back to the client of the API.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-on-behalf-of-flow)
+    ///
+    /// [with_token_cache]: #method.with_token_cache
+    /// [claims]: ./resource/struct.OAuth2ErrorResponse.html#structfield.claims
+    pub async fn login_on_behalf_of(
+        &self,
+        assertion: &str,
+        client_credential: &ClientCredential,
+    ) -> Result<TokenResponse> {
+        assert!(
+            *client_credential != ClientCredential::None,
+            "On-behalf-of flow requires a client secret or assertion.",
         );
 
         self.request_token(
-            true,
+            self.scopes.offline_access(),
             [
                 ("client_id", &self.client_id as &str),
-                ("grant_type", "refresh_token"),
-                ("redirect_uri", &self.redirect_uri),
-                ("refresh_token", refresh_token),
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", assertion),
+                ("scope", &self.scopes.to_scope_string()),
+                ("requested_token_use", "on_behalf_of"),
             ]
             .into_iter()
-            .chain(client_credential.params()),
+            .chain(client_credential.resolve().params()),
         )
         .await
     }
+
+    /// Start a device code flow by requesting a device code and a user code.
+    ///
+    /// The user should visit [`DeviceCodeResponse::verification_uri`] on another device and
+    /// enter [`DeviceCodeResponse::user_code`]. Meanwhile, call
+    /// [`login_with_device_code`][login_with_device_code] to wait for the authorization.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-device-code#device-authorization-request)
+    ///
+    /// [login_with_device_code]: #method.login_with_device_code
+    pub async fn request_device_code(&self) -> Result<DeviceCodeResponse> {
+        let url = self.endpoint_url("devicecode");
+        let params = [
+            ("client_id", &self.client_id as &str),
+            ("scope", &self.scopes.to_scope_string()),
+        ];
+        let resp = self.client.post(url).form(&params).send().await?;
+        Ok(handle_oauth2_error_response(resp).await?.json().await?)
+    }
+
+    /// Login using a device code from [`request_device_code`][request_device_code].
+    ///
+    /// This polls the token endpoint with the interval given by the server until the user
+    /// completes or declines the authorization, or the device code expires.
+    /// `authorization_pending` is waited and `slow_down` increases the polling interval.
+    ///
+    /// # Errors
+    /// Other OAuth2 errors are returned as is, which can be inspected via
+    /// [`Error::oauth2_error_response`]. Specifically, `expired_token` means the user did not
+    /// finish the authorization in time and the flow should be restarted, while
+    /// `authorization_declined` means the user denied the request.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-device-code#authenticating-the-user)
+    ///
+    /// [request_device_code]: #method.request_device_code
+    pub async fn login_with_device_code(
+        &self,
+        device_code: &DeviceCodeResponse,
+    ) -> Result<TokenResponse> {
+        // https://datatracker.ietf.org/doc/html/rfc8628#section-3.5
+        const SLOW_DOWN_INCREMENT_SECS: u64 = 5;
+
+        let mut interval_secs = device_code.interval_secs;
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+            let ret = self
+                .request_token(
+                    self.scopes.offline_access(),
+                    [
+                        ("client_id", &self.client_id as &str),
+                        ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                        ("device_code", &device_code.device_code),
+                    ]
+                    .into_iter(),
+                )
+                .await;
+            let err = match ret {
+                Ok(token_resp) => return Ok(self.cache_token(token_resp).await),
+                Err(err) => err,
+            };
+            match err.oauth2_error_response().map(|resp| &*resp.error) {
+                Some("authorization_pending") => {}
+                Some("slow_down") => interval_secs += SLOW_DOWN_INCREMENT_SECS,
+                _ => return Err(err),
+            }
+        }
+    }
+}
+
+/// A PKCE (Proof Key for Code Exchange) code verifier.
+///
+/// # See also
+/// [RFC 7636](https://datatracker.ietf.org/doc/html/rfc7636)
+#[derive(Clone, PartialEq, Eq)]
+pub struct PkceCodeVerifier(String);
+
+impl PkceCodeVerifier {
+    /// Generate a new random code verifier.
+    ///
+    /// # Panics
+    /// Panic if the system random number generator fails.
+    #[must_use]
+    pub fn new_random() -> Self {
+        Self(random_token::<32>())
+    }
+
+    /// Check and wrap an existing code verifier, eg. one restored from a session storage.
+    ///
+    /// Returns `None` if it is not 43 to 128 characters long, or contains characters other
+    /// than `[A-Za-z0-9-._~]`.
+    #[must_use]
+    pub fn new(verifier: impl Into<String>) -> Option<Self> {
+        let verifier = verifier.into();
+        let valid = (43..=128).contains(&verifier.len())
+            && verifier
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b));
+        valid.then_some(Self(verifier))
+    }
+
+    /// View the code verifier as `&str`.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Get the `S256` code challenge derived from this code verifier.
+    #[must_use]
+    pub fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl fmt::Debug for PkceCodeVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PkceCodeVerifier").finish_non_exhaustive()
+    }
+}
+
+/// Generate `N` random bytes encoded in URL-safe base64.
+fn random_token<const N: usize>() -> String {
+    let mut buf = [0u8; N];
+    getrandom::fill(&mut buf).expect("Failed to generate random bytes");
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// An authorization request for code flow, returned by [`Auth::code_auth_request`].
+#[derive(Debug, Clone)]
+pub struct CodeAuthRequest {
+    url: Url,
+    code_verifier: PkceCodeVerifier,
+    state: String,
+}
+
+impl CodeAuthRequest {
+    /// Get the URL for web browser to login.
+    #[must_use]
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Get the code verifier to be passed to [`Auth::login_with_code`].
+    #[must_use]
+    pub fn code_verifier(&self) -> &PkceCodeVerifier {
+        &self.code_verifier
+    }
+
+    /// Get the random `state` sent in the URL.
+    #[must_use]
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    /// Parse the full URL redirected to after login, check its `state`, and return the code.
+    ///
+    /// # Errors
+    /// - If the authorization server responds an error, return an error with
+    ///   [`Error::oauth2_error_response`] built from `error` and `error_description`.
+    /// - If `redirect_url` is not a valid URL, has no code, or has a `state` mismatching
+    ///   [`CodeAuthRequest::state`] (which may indicate a CSRF attack), return an error
+    ///   without any error response.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-auth-code-flow#successful-response)
+    pub fn parse_redirect_url(&self, redirect_url: &str) -> Result<String> {
+        let url = Url::parse(redirect_url)
+            .map_err(|_| Error::unexpected_response("Invalid redirect URL"))?;
+        let (mut code, mut state, mut error, mut error_description) = (None, None, None, None);
+        for (key, value) in url.query_pairs() {
+            match &*key {
+                "code" => code = Some(value.into_owned()),
+                "state" => state = Some(value),
+                "error" => error = Some(value.into_owned()),
+                "error_description" => error_description = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        if state.as_deref() != Some(&self.state) {
+            return Err(Error::unexpected_response(
+                "Mismatched `state` in redirect URL",
+            ));
+        }
+        if let Some(error) = error {
+            return Err(Error::from_oauth2_redirect_error(OAuth2ErrorResponse {
+                error,
+                error_description: error_description.unwrap_or_default(),
+                error_codes: None,
+                timestamp: None,
+                trace_id: None,
+                correlation_id: None,
+                claims: None,
+                suberror: None,
+            }));
+        }
+        code.ok_or_else(|| Error::unexpected_response("Missing `code` in redirect URL"))
+    }
+}
+
+/// The device code and user code returned by [`Auth::request_device_code`].
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-device-code#device-authorization-response)
+#[derive(Clone, Deserialize)]
+#[non_exhaustive]
+pub struct DeviceCodeResponse {
+    /// The code used to poll for the token. It should not be shown to the user.
+    pub device_code: String,
+    /// A short string shown to the user to identify the session on a secondary device.
+    pub user_code: String,
+    /// The URI the user should go to with the `user_code` in order to sign in.
+    pub verification_uri: String,
+    /// How long the `device_code` and `user_code` are valid (in seconds).
+    #[serde(rename = "expires_in")]
+    pub expires_in_secs: u64,
+    /// The number of seconds the client should wait between polling requests.
+    #[serde(rename = "interval", default = "default_device_code_interval_secs")]
+    pub interval_secs: u64,
+    /// A human-readable string with instructions for the user.
+    pub message: Option<String>,
+}
+
+fn default_device_code_interval_secs() -> u64 {
+    // https://datatracker.ietf.org/doc/html/rfc8628#section-3.2
+    5
+}
+
+impl fmt::Debug for DeviceCodeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceCodeResponse")
+            .field("user_code", &self.user_code)
+            .field("verification_uri", &self.verification_uri)
+            .field("expires_in_secs", &self.expires_in_secs)
+            .field("interval_secs", &self.interval_secs)
+            .field("message", &self.message)
+            .finish_non_exhaustive()
+    }
 }
 
 /// Credential of client for code redeemption.
 ///
 /// See:
 /// <https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-auth-code-flow#redeem-a-code-for-an-access-token>
-#[derive(Default, Clone, PartialEq, Eq)]
+#[derive(Default, Clone)]
 #[non_exhaustive]
 pub enum ClientCredential {
     /// Nothing.
@@ -315,24 +798,65 @@ pub enum ClientCredential {
     /// See:
     /// <https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-auth-code-flow#request-an-access-token-with-a-certificate-credential>
     Assertion(String),
+    /// Assertions signed by [`ClientAssertion`], which are renewed automatically on expiration.
+    ///
+    /// This requires feature `client-assertion`.
+    #[cfg(feature = "client-assertion")]
+    SignedAssertion(std::sync::Arc<ClientAssertion>),
+}
+
+impl PartialEq for ClientCredential {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::None, Self::None) => true,
+            (Self::Secret(a), Self::Secret(b)) | (Self::Assertion(a), Self::Assertion(b)) => a == b,
+            #[cfg(feature = "client-assertion")]
+            (Self::SignedAssertion(a), Self::SignedAssertion(b)) => std::sync::Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
 }
 
+impl Eq for ClientCredential {}
+
 impl fmt::Debug for ClientCredential {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::None => write!(f, "None"),
             Self::Secret(_) => f.debug_struct("Secret").finish_non_exhaustive(),
             Self::Assertion(_) => f.debug_struct("Assertion").finish_non_exhaustive(),
+            #[cfg(feature = "client-assertion")]
+            Self::SignedAssertion(a) => f.debug_tuple("SignedAssertion").field(a).finish(),
         }
     }
 }
 
 impl ClientCredential {
+    /// Get a valid static credential, signing a new assertion if necessary.
+    fn resolve(&self) -> ResolvedCredential<'_> {
+        match self {
+            Self::None => ResolvedCredential::None,
+            Self::Secret(s) => ResolvedCredential::Secret(s),
+            Self::Assertion(s) => ResolvedCredential::Assertion(Cow::Borrowed(s)),
+            #[cfg(feature = "client-assertion")]
+            Self::SignedAssertion(a) => ResolvedCredential::Assertion(Cow::Owned(a.assertion())),
+        }
+    }
+}
+
+/// A [`ClientCredential`] ready to be sent, with signed assertions already generated.
+enum ResolvedCredential<'a> {
+    None,
+    Secret(&'a str),
+    Assertion(Cow<'a, str>),
+}
+
+impl ResolvedCredential<'_> {
     fn params(&self) -> impl Iterator<Item = (&str, &str)> {
         let (a, b) = match self {
-            ClientCredential::None => (None, None),
-            ClientCredential::Secret(s) => (Some(("client_secret", &**s)), None),
-            ClientCredential::Assertion(s) => (
+            Self::None => (None, None),
+            Self::Secret(s) => (Some(("client_secret", *s)), None),
+            Self::Assertion(s) => (
                 Some((
                     "client_assertion_type",
                     "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
@@ -348,13 +872,16 @@ impl ClientCredential {
 ///
 /// # See also
 /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/auth-v2-user?view=graph-rest-1.0#token-response)
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[non_exhaustive]
 pub struct TokenResponse {
     /// Indicates the token type value. The only type that Azure AD supports is Bearer.
     pub token_type: String,
     /// A list of the Microsoft Graph permissions that the `access_token` is valid for.
-    #[serde(deserialize_with = "space_separated_strings")]
+    #[serde(
+        serialize_with = "serialize_space_separated_strings",
+        deserialize_with = "space_separated_strings"
+    )]
     pub scope: Vec<String>,
     /// How long the access token is valid (in seconds).
     #[serde(rename = "expires_in")]
@@ -375,6 +902,19 @@ pub struct TokenResponse {
     pub refresh_token: Option<String>,
 }
 
+impl TokenResponse {
+    /// Get scopes in `requested` which are not granted in [`scope`][scope], eg. because the
+    /// user or administrator did not consent to them.
+    ///
+    /// See [`ScopeSet::missing_in`] for how scopes are compared.
+    ///
+    /// [scope]: #structfield.scope
+    #[must_use]
+    pub fn missing_scopes<'a>(&self, requested: &'a ScopeSet) -> Vec<&'a Scope> {
+        requested.missing_in(&self.scope)
+    }
+}
+
 impl fmt::Debug for TokenResponse {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("TokenResponse")
@@ -385,6 +925,16 @@ impl fmt::Debug for TokenResponse {
     }
 }
 
+fn serialize_space_separated_strings<S>(
+    strings: &[String],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&strings.join(" "))
+}
+
 fn space_separated_strings<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
 where
     D: serde::de::Deserializer<'de>,
@@ -427,4 +977,150 @@ mod tests {
             "https://login.microsoftonline.com/consumers/oauth2/v2.0/authorize?client_id=some-client-id&scope=files.readwrite+offline_access&redirect_uri=http%3A%2F%2Fexample.com&response_type=code",
         );
     }
+
+    #[test]
+    #[allow(deprecated)]
+    fn permission_from_scopes() {
+        let perm = Permission::new_read()
+            .access_shared(true)
+            .offline_access(true);
+        let auth = Auth::new("some-client-id", perm, "http://example.com", Tenant::Common);
+        let got = auth.permission();
+        assert!(!got.write && got.access_shared && got.offline_access);
+
+        let scopes = ScopeSet::new()
+            .with(Scope::FILES_READ_WRITE)
+            .with(Scope::USER_READ);
+        let auth = Auth::new(
+            "some-client-id",
+            scopes,
+            "http://example.com",
+            Tenant::Common,
+        );
+        let got = auth.permission();
+        assert!(got.write && !got.access_shared && !got.offline_access);
+    }
+
+    #[test]
+    fn national_cloud() {
+        let auth = Auth::new(
+            "some-client-id",
+            Permission::new_read(),
+            "http://example.com",
+            Tenant::Issuer("some-tenant".to_owned()),
+        )
+        .with_cloud(&Cloud::china());
+        assert_eq!(
+            auth.endpoint_url("token").as_str(),
+            "https://login.chinacloudapi.cn/some-tenant/oauth2/v2.0/token",
+        );
+        assert_eq!(
+            auth.cloud().graph_default_scope(),
+            "https://microsoftgraph.chinacloudapi.cn/.default",
+        );
+
+        let custom = Cloud::new(
+            Url::parse("https://login.example.com/prefix/").unwrap(),
+            Url::parse("https://graph.example.com").unwrap(),
+        );
+        assert_eq!(
+            auth.with_cloud(&custom)
+                .code_auth_url()
+                .as_str()
+                .split('?')
+                .next(),
+            Some("https://login.example.com/prefix/some-tenant/oauth2/v2.0/authorize"),
+        );
+    }
+
+    #[test]
+    fn pkce() {
+        // From RFC 7636 Appendix B.
+        let verifier =
+            PkceCodeVerifier::new("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk").unwrap();
+        assert_eq!(
+            verifier.code_challenge(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+        );
+        assert!(PkceCodeVerifier::new("too-short").is_none());
+        assert!(PkceCodeVerifier::new("+".repeat(43)).is_none());
+
+        let random = PkceCodeVerifier::new_random();
+        assert_eq!(PkceCodeVerifier::new(random.as_str()), Some(random.clone()));
+        assert_ne!(PkceCodeVerifier::new_random(), random);
+
+        let auth = Auth::new(
+            "some-client-id",
+            Permission::new_read(),
+            "http://example.com",
+            Tenant::Consumers,
+        );
+        let req = auth.code_auth_request();
+        let challenge = req.code_verifier().code_challenge();
+        assert!(req
+            .url()
+            .as_str()
+            .starts_with(auth.code_auth_url().as_str()));
+        assert!(req
+            .url()
+            .query_pairs()
+            .any(|(k, v)| k == "code_challenge" && v == challenge));
+    }
+
+    #[test]
+    fn parse_redirect_url() {
+        let auth = Auth::new(
+            "some-client-id",
+            Permission::new_read(),
+            "http://example.com/redirect",
+            Tenant::Consumers,
+        );
+        let req = auth.code_auth_request();
+        let state = req.state();
+        assert_eq!(state.len(), 22);
+        assert!(req
+            .url()
+            .query_pairs()
+            .any(|(k, v)| k == "state" && v == state));
+
+        let url = format!("http://example.com/redirect?code=some-code&state={state}");
+        assert_eq!(req.parse_redirect_url(&url).unwrap(), "some-code");
+
+        let url = format!(
+            "http://example.com/redirect?error=access_denied&error_description=The+user+denied&state={state}",
+        );
+        let err = req.parse_redirect_url(&url).unwrap_err();
+        let resp = err.oauth2_error_response().unwrap();
+        assert_eq!(resp.error, "access_denied");
+        assert_eq!(resp.error_description, "The user denied");
+        assert_eq!(err.status_code(), None);
+
+        for url in [
+            "http://example.com/redirect?code=some-code",
+            "http://example.com/redirect?code=some-code&state=forged",
+            &format!("http://example.com/redirect?state={state}"),
+            "some-code",
+        ] {
+            let err = req.parse_redirect_url(url).unwrap_err();
+            assert!(err.oauth2_error_response().is_none(), "{url}");
+        }
+    }
+
+    #[test]
+    fn device_code_response() {
+        let resp: DeviceCodeResponse = serde_json::from_str(
+            r#"{
+                "device_code": "some-device-code",
+                "user_code": "ABCD1234",
+                "verification_uri": "https://microsoft.com/devicelogin",
+                "expires_in": 900
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(resp.user_code, "ABCD1234");
+        assert_eq!(resp.expires_in_secs, 900);
+        assert_eq!(resp.interval_secs, 5);
+        assert_eq!(resp.message, None);
+        assert!(!format!("{resp:?}").contains("some-device-code"));
+    }
 }
@@ -0,0 +1,299 @@
+use std::{
+    future::poll_fn,
+    io,
+    net::{Ipv4Addr, Ipv6Addr},
+    pin::Pin,
+    task::Poll,
+    time::Duration,
+};
+
+use super::CodeAuthRequest;
+use crate::error::Result;
+use futures_core::Stream as _;
+use futures_util::stream::FuturesUnordered;
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+};
+
+/// The maximum size of the HTTP request head we accept.
+const MAX_REQUEST_HEAD_LEN: usize = 8 << 10;
+
+/// The timeout of reading the HTTP request head of a connection.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A one-shot HTTP listener on the loopback interface, to capture the redirect of code flow.
+///
+/// This is for desktop applications, so that users do not need to copy the redirected URL
+/// manually. The application should be registered with redirect URI `http://localhost`
+/// (port is ignored by Microsoft identity platform for loopback addresses).
+///
+/// # Example
+/// ```
+/// use onedrive_api::{Auth, ClientCredential, LoopbackRedirectListener, Permission, Tenant};
+/// use std::time::Duration;
+///
+/// # async fn run() -> onedrive_api::Result<()> {
+/// let listener = LoopbackRedirectListener::bind(0).await?;
+/// let auth = Auth::new(
+///     "<...CLIENT ID...>",
+///     Permission::new_read().offline_access(true),
+///     listener.redirect_uri(),
+///     Tenant::Consumers,
+/// );
+/// let request = auth.code_auth_request();
+/// let code = listener
+///     .receive_code_with_opener(&request, Duration::from_secs(300), |url| {
+///         // Open `url` in browser here, eg. using crate `open`.
+///         Ok(())
+///     })
+///     .await?;
+/// let token = auth
+///     .login_with_code(&code, Some(request.code_verifier()), &ClientCredential::None)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/reply-url#localhost-exceptions)
+#[derive(Debug)]
+pub struct LoopbackRedirectListener {
+    listeners: Vec<TcpListener>,
+    redirect_uri: String,
+}
+
+impl LoopbackRedirectListener {
+    /// Start listening on `localhost` with the given port.
+    ///
+    /// If `port` is 0, a free port is picked by the system.
+    ///
+    /// Both IPv4 and IPv6 loopback addresses are listened if possible, since `localhost` may be
+    /// resolved to either of them by browsers.
+    pub async fn bind(port: u16) -> Result<Self> {
+        let v4 = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let port = v4.local_addr()?.port();
+        let mut listeners = vec![v4];
+        // IPv6 may be unavailable. It is fine to use IPv4 only.
+        if let Ok(v6) = TcpListener::bind((Ipv6Addr::LOCALHOST, port)).await {
+            listeners.push(v6);
+        }
+        Ok(Self {
+            listeners,
+            redirect_uri: format!("http://localhost:{port}"),
+        })
+    }
+
+    /// Get the redirect URI pointing to this listener.
+    ///
+    /// It should be used as the `redirect_uri` of [`Auth`][auth] to create the
+    /// [`CodeAuthRequest`] for [`receive_code`][receive_code].
+    ///
+    /// [auth]: ./struct.Auth.html
+    /// [receive_code]: #method.receive_code
+    #[must_use]
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    /// Wait for the browser to be redirected to this listener, and return the code from it.
+    ///
+    /// The listener is shut down after receiving the redirect, or on timeout.
+    /// Unrelated requests (eg. `/favicon.ico`) are answered with `404 Not Found` and ignored.
+    ///
+    /// # Errors
+    /// Return an IO error of kind [`TimedOut`][io::ErrorKind::TimedOut] if no redirect is
+    /// received within `timeout`. Other errors are the same as
+    /// [`CodeAuthRequest::parse_redirect_url`].
+    pub async fn receive_code(
+        self,
+        request: &CodeAuthRequest,
+        timeout: Duration,
+    ) -> Result<String> {
+        tokio::time::timeout(timeout, self.serve(request))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Timeout waiting for redirect"))?
+    }
+
+    /// Open the authorization URL of `request` by `open`, then wait for the redirect like
+    /// [`receive_code`][receive_code].
+    ///
+    /// `open` usually launches the browser, eg. by `open::that` of crate `open`. It is called
+    /// after the listener is ready, so the redirect is never missed.
+    ///
+    /// # Errors
+    /// Return the IO error from `open` if it fails. Other errors are the same as
+    /// [`receive_code`][receive_code].
+    ///
+    /// [receive_code]: #method.receive_code
+    pub async fn receive_code_with_opener(
+        self,
+        request: &CodeAuthRequest,
+        timeout: Duration,
+        open: impl FnOnce(&str) -> io::Result<()>,
+    ) -> Result<String> {
+        open(request.url().as_str())?;
+        self.receive_code(request, timeout).await
+    }
+
+    /// Accept connections and handle them concurrently, until the first valid redirect.
+    ///
+    /// Browsers may open idle speculative connections, which must not block the real one.
+    async fn serve(&self, request: &CodeAuthRequest) -> Result<String> {
+        let mut connections = FuturesUnordered::new();
+        poll_fn(|cx| {
+            for listener in &self.listeners {
+                while let Poll::Ready(ret) = listener.poll_accept(cx) {
+                    let (stream, _) = ret?;
+                    connections.push(Box::pin(self.handle_connection(stream, request)));
+                }
+            }
+            while let Poll::Ready(Some(ret)) = Pin::new(&mut connections).poll_next(cx) {
+                if let Some(ret) = ret {
+                    return Poll::Ready(ret);
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Handle a connection, and return `None` if it is not the redirect.
+    async fn handle_connection(
+        &self,
+        mut stream: TcpStream,
+        request: &CodeAuthRequest,
+    ) -> Option<Result<String>> {
+        // Errors of individual connections are ignored, since they may come from
+        // speculative connections of browsers.
+        let target = tokio::time::timeout(READ_TIMEOUT, read_request_target(&mut stream))
+            .await
+            .ok()?
+            .ok()??;
+        if target != "/" && !target.starts_with("/?") {
+            let _ = respond(&mut stream, "404 Not Found", "Not found.").await;
+            return None;
+        }
+
+        let ret = request.parse_redirect_url(&format!("{}{}", self.redirect_uri, target));
+        let body = match &ret {
+            Ok(_) => "Login succeeded. You can close this window now.".to_owned(),
+            Err(err) => format!("Login failed: {err}"),
+        };
+        let _ = respond(&mut stream, "200 OK", &body).await;
+        Some(ret)
+    }
+}
+
+/// Read the HTTP request head and return the request target of `GET` request.
+async fn read_request_target(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut buf = Vec::new();
+    while !buf.ends_with(b"\r\n\r\n") {
+        if buf.len() >= MAX_REQUEST_HEAD_LEN {
+            return Ok(None);
+        }
+        let mut chunk = [0u8; 1024];
+        let len = stream.read(&mut chunk).await?;
+        if len == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..len]);
+    }
+
+    let head = String::from_utf8_lossy(&buf);
+    let mut parts = head.lines().next().unwrap_or_default().split(' ');
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Ok(Some(target.to_owned())),
+        _ => Ok(None),
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let resp = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len(),
+    );
+    stream.write_all(resp.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Auth, Permission, Tenant};
+
+    async fn send_request(port: u16, target: &str) -> String {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        let req = format!("GET {target} HTTP/1.1\r\nHost: localhost:{port}\r\n\r\n");
+        stream.write_all(req.as_bytes()).await.unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await.unwrap();
+        resp
+    }
+
+    #[tokio::test]
+    async fn receive_code() {
+        let listener = LoopbackRedirectListener::bind(0).await.unwrap();
+        let port: u16 = listener
+            .redirect_uri()
+            .strip_prefix("http://localhost:")
+            .unwrap()
+            .parse()
+            .unwrap();
+        let auth = Auth::new(
+            "some-client-id",
+            Permission::new_read(),
+            listener.redirect_uri(),
+            Tenant::Consumers,
+        );
+        let request = auth.code_auth_request();
+        let state = request.state().to_owned();
+
+        let (url_tx, url_rx) = tokio::sync::oneshot::channel();
+        let server = tokio::spawn(async move {
+            listener
+                .receive_code_with_opener(&request, Duration::from_secs(10), |url| {
+                    url_tx.send(url.to_owned()).unwrap();
+                    Ok(())
+                })
+                .await
+        });
+        assert!(url_rx.await.unwrap().contains(&state));
+
+        // An idle connection, like a speculative one from browsers, must not block others.
+        let _idle = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        let resp = send_request(port, "/favicon.ico").await;
+        assert!(resp.starts_with("HTTP/1.1 404 "), "{resp}");
+        let resp = send_request(port, &format!("/?code=some-code&state={state}")).await;
+        assert!(resp.starts_with("HTTP/1.1 200 "), "{resp}");
+
+        assert_eq!(server.await.unwrap().unwrap(), "some-code");
+    }
+
+    #[tokio::test]
+    async fn receive_code_timeout() {
+        let listener = LoopbackRedirectListener::bind(0).await.unwrap();
+        let auth = Auth::new(
+            "some-client-id",
+            Permission::new_read(),
+            listener.redirect_uri(),
+            Tenant::Consumers,
+        );
+        let request = auth.code_auth_request();
+        let err = listener
+            .receive_code(&request, Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), None);
+        assert!(err.oauth2_error_response().is_none());
+    }
+}
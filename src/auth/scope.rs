@@ -0,0 +1,258 @@
+use std::{borrow::Cow, fmt};
+
+use super::Permission;
+use crate::Cloud;
+
+/// A single OAuth2 scope, eg. a Microsoft Graph permission.
+///
+/// Common scopes are provided as associated constants. Others can be created by
+/// [`Scope::new`] or [`Scope::with_resource`].
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/permissions-reference)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Scope(Cow<'static, str>);
+
+impl Scope {
+    /// `Files.Read`
+    pub const FILES_READ: Self = Self::from_static("Files.Read");
+    /// `Files.Read.All`
+    pub const FILES_READ_ALL: Self = Self::from_static("Files.Read.All");
+    /// `Files.ReadWrite`
+    pub const FILES_READ_WRITE: Self = Self::from_static("Files.ReadWrite");
+    /// `Files.ReadWrite.All`
+    pub const FILES_READ_WRITE_ALL: Self = Self::from_static("Files.ReadWrite.All");
+    /// `Files.ReadWrite.AppFolder`
+    pub const FILES_READ_WRITE_APP_FOLDER: Self = Self::from_static("Files.ReadWrite.AppFolder");
+    /// `Sites.Read.All`
+    pub const SITES_READ_ALL: Self = Self::from_static("Sites.Read.All");
+    /// `Sites.ReadWrite.All`
+    pub const SITES_READ_WRITE_ALL: Self = Self::from_static("Sites.ReadWrite.All");
+    /// `User.Read`
+    pub const USER_READ: Self = Self::from_static("User.Read");
+    /// `offline_access`, which is required to get a [`TokenResponse::refresh_token`].
+    ///
+    /// [`TokenResponse::refresh_token`]: ./struct.TokenResponse.html#structfield.refresh_token
+    pub const OFFLINE_ACCESS: Self = Self::from_static("offline_access");
+    /// `openid` of OIDC.
+    pub const OPENID: Self = Self::from_static("openid");
+    /// `profile` of OIDC.
+    pub const PROFILE: Self = Self::from_static("profile");
+    /// `email` of OIDC.
+    pub const EMAIL: Self = Self::from_static("email");
+
+    const fn from_static(scope: &'static str) -> Self {
+        Self(Cow::Borrowed(scope))
+    }
+
+    /// Create a scope from its string form, eg. `Mail.Read` or `api://some-app-id/some.scope`.
+    pub fn new(scope: impl Into<String>) -> Self {
+        Self(Cow::Owned(scope.into()))
+    }
+
+    /// Create a resource-qualified scope, eg. `https://graph.microsoft.com/Files.Read` from
+    /// resource `https://graph.microsoft.com` and permission `Files.Read`.
+    #[must_use]
+    pub fn with_resource(resource: &str, permission: &str) -> Self {
+        Self::new(format!("{}/{}", resource.trim_end_matches('/'), permission))
+    }
+
+    /// Get the string form of this scope.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is an OIDC scope or `offline_access`, which are not permissions
+    /// of resources and may not be listed in [`TokenResponse::scope`].
+    ///
+    /// [`TokenResponse::scope`]: ./struct.TokenResponse.html#structfield.scope
+    fn is_special(&self) -> bool {
+        [
+            Self::OFFLINE_ACCESS,
+            Self::OPENID,
+            Self::PROFILE,
+            Self::EMAIL,
+        ]
+        .iter()
+        .any(|s| self.0.eq_ignore_ascii_case(&s.0))
+    }
+
+    /// Whether this is the `.default` scope of some resource, which is expanded by the server.
+    fn is_default(&self) -> bool {
+        self.0 == ".default" || self.0.ends_with("/.default")
+    }
+
+    /// Normalize for comparison. Scopes are case-insensitive, and the Microsoft Graph resource
+    /// prefix is optional.
+    fn normalized(scope: &str) -> String {
+        Cloud::strip_graph_resource(scope).to_ascii_lowercase()
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&'static str> for Scope {
+    fn from(scope: &'static str) -> Self {
+        Self::from_static(scope)
+    }
+}
+
+impl From<String> for Scope {
+    fn from(scope: String) -> Self {
+        Self::new(scope)
+    }
+}
+
+/// A set of scopes to request, used by [`Auth`][auth].
+///
+/// [`Permission`] can be converted into it for common OneDrive usages.
+///
+/// # Example
+/// ```
+/// use onedrive_api::{Scope, ScopeSet};
+///
+/// let scopes = ScopeSet::new()
+///     .with(Scope::FILES_READ_WRITE_APP_FOLDER)
+///     .with(Scope::USER_READ)
+///     .with(Scope::OFFLINE_ACCESS);
+/// assert_eq!(
+///     scopes.to_scope_string(),
+///     "Files.ReadWrite.AppFolder User.Read offline_access",
+/// );
+/// ```
+///
+/// [auth]: ./struct.Auth.html
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScopeSet {
+    scopes: Vec<Scope>,
+}
+
+impl ScopeSet {
+    /// Create an empty set.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a scope. Duplicated scopes are ignored.
+    #[must_use]
+    pub fn with(mut self, scope: impl Into<Scope>) -> Self {
+        let scope = scope.into();
+        if !self.contains(&scope) {
+            self.scopes.push(scope);
+        }
+        self
+    }
+
+    /// Whether the set contains `scope`, compared case-insensitively.
+    #[must_use]
+    pub fn contains(&self, scope: &Scope) -> bool {
+        let scope = Scope::normalized(scope.as_str());
+        self.scopes
+            .iter()
+            .any(|s| Scope::normalized(s.as_str()) == scope)
+    }
+
+    /// Whether the set contains [`Scope::OFFLINE_ACCESS`].
+    #[must_use]
+    pub fn offline_access(&self) -> bool {
+        self.contains(&Scope::OFFLINE_ACCESS)
+    }
+
+    /// Iterate over all scopes in the set, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = &Scope> + '_ {
+        self.scopes.iter()
+    }
+
+    /// Get the space-separated scope string for OAuth2 requests.
+    #[must_use]
+    pub fn to_scope_string(&self) -> String {
+        self.scopes
+            .iter()
+            .map(Scope::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Get requested scopes which are not in `granted`, eg. [`TokenResponse::scope`].
+    ///
+    /// Scopes are compared case-insensitively, and the Microsoft Graph resource prefix is
+    /// ignored. OIDC scopes, `offline_access` and `.default` scopes are never reported
+    /// as missing, since they are not always listed in granted scopes.
+    ///
+    /// [`TokenResponse::scope`]: ./struct.TokenResponse.html#structfield.scope
+    pub fn missing_in<'a, S: AsRef<str>>(&'a self, granted: &[S]) -> Vec<&'a Scope> {
+        let granted = granted
+            .iter()
+            .map(|s| Scope::normalized(s.as_ref()))
+            .collect::<Vec<_>>();
+        self.scopes
+            .iter()
+            .filter(|s| !s.is_special() && !s.is_default())
+            .filter(|s| !granted.contains(&Scope::normalized(s.as_str())))
+            .collect()
+    }
+}
+
+impl From<Permission> for ScopeSet {
+    fn from(perm: Permission) -> Self {
+        perm.to_scope_string().split(' ').map(Scope::new).collect()
+    }
+}
+
+impl<S: Into<Scope>> FromIterator<S> for ScopeSet {
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::new(), Self::with)
+    }
+}
+
+impl<'a> IntoIterator for &'a ScopeSet {
+    type Item = &'a Scope;
+    type IntoIter = std::slice::Iter<'a, Scope>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.scopes.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_permission() {
+        let scopes = ScopeSet::from(Permission::new_read().write(true).offline_access(true));
+        assert_eq!(scopes.to_scope_string(), "files.readwrite offline_access");
+        assert!(scopes.offline_access());
+        assert!(scopes.contains(&Scope::FILES_READ_WRITE));
+        assert!(!ScopeSet::from(Permission::new_read()).offline_access());
+    }
+
+    #[test]
+    fn missing_in() {
+        let scopes = ScopeSet::new()
+            .with(Scope::FILES_READ_ALL)
+            .with(Scope::with_resource(
+                "https://graph.microsoft.com/",
+                "User.Read",
+            ))
+            .with(Scope::SITES_READ_ALL)
+            .with("https://some.resource/.default")
+            .with(Scope::OPENID)
+            .with(Scope::OFFLINE_ACCESS)
+            .with("files.read.all");
+        assert_eq!(scopes.iter().count(), 6);
+
+        let granted = ["https://graph.microsoft.com/Files.Read.All", "user.read"];
+        assert_eq!(scopes.missing_in(&granted), [&Scope::SITES_READ_ALL]);
+        let granted = ["https://graph.microsoft.us/files.read.all", "User.Read"];
+        assert_eq!(scopes.missing_in(&granted), [&Scope::SITES_READ_ALL]);
+        let granted = ["Files.Read.All", "User.Read", "Sites.Read.All", "profile"];
+        assert!(scopes.missing_in(&granted).is_empty());
+    }
+}
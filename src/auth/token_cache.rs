@@ -0,0 +1,319 @@
+use std::{
+    fmt,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use super::TokenResponse;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// A persistent storage for serialized tokens, used by [`TokenCache`].
+///
+/// The stored data contains secrets. Implementations should protect it properly.
+pub trait TokenStorage: fmt::Debug + Send + Sync {
+    /// Load the stored data, or `None` if nothing is stored yet.
+    fn load(&self) -> Pin<Box<dyn Future<Output = Result<Option<String>>> + Send + '_>>;
+
+    /// Store the data, replacing the previous one.
+    fn store<'a>(&'a self, data: &'a str) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+}
+
+/// A [`TokenStorage`] in memory, which is lost when dropped.
+///
+/// This is mainly for testing, or sharing tokens between components in one process.
+#[derive(Default)]
+pub struct MemoryTokenStorage {
+    data: Mutex<Option<String>>,
+}
+
+impl MemoryTokenStorage {
+    /// Create an empty storage.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for MemoryTokenStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Skip `data`.
+        f.debug_struct("MemoryTokenStorage").finish_non_exhaustive()
+    }
+}
+
+impl TokenStorage for MemoryTokenStorage {
+    fn load(&self) -> Pin<Box<dyn Future<Output = Result<Option<String>>> + Send + '_>> {
+        let data = self.data.lock().unwrap().clone();
+        Box::pin(async move { Ok(data) })
+    }
+
+    fn store<'a>(&'a self, data: &'a str) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        *self.data.lock().unwrap() = Some(data.to_owned());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// A [`TokenStorage`] in a local file.
+///
+/// The file is replaced atomically by writing to a temporary file in the same directory and
+/// renaming it, so it will not be corrupted if the process is killed during writing, or
+/// written by multiple processes concurrently. On Unix, the written file always has permission
+/// `0600`, even if the file existed with other permissions before.
+#[derive(Debug, Clone)]
+pub struct FileTokenStorage {
+    path: PathBuf,
+}
+
+impl FileTokenStorage {
+    /// Create a storage using the file at `path`. The file is not touched until used.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Get the path to the file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn load_file(&self) -> Result<Option<String>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn store_file(&self, data: &str) -> Result<()> {
+        use tokio::io::AsyncWriteExt as _;
+
+        // Unique among processes and concurrent calls, so that writers never share a file.
+        let rand = getrandom::u32().map_err(|err| io::Error::other(err.to_string()))?;
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(format!(".{}.{rand:08x}.tmp", std::process::id()));
+        let tmp_path = self.path.with_file_name(tmp_name);
+
+        // The file is always newly created, so the mode always applies.
+        let mut opts = tokio::fs::OpenOptions::new();
+        opts.write(true).create_new(true);
+        #[cfg(unix)]
+        opts.mode(0o600);
+        let mut file = opts.open(&tmp_path).await?;
+        let ret = async {
+            file.write_all(data.as_bytes()).await?;
+            file.sync_all().await?;
+            drop(file);
+            tokio::fs::rename(&tmp_path, &self.path).await
+        }
+        .await;
+        if ret.is_err() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+        }
+        Ok(ret?)
+    }
+}
+
+impl TokenStorage for FileTokenStorage {
+    fn load(&self) -> Pin<Box<dyn Future<Output = Result<Option<String>>> + Send + '_>> {
+        Box::pin(self.load_file())
+    }
+
+    fn store<'a>(&'a self, data: &'a str) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(self.store_file(data))
+    }
+}
+
+/// A [`TokenResponse`] with its absolute expiration time, stored in [`TokenCache`].
+#[derive(Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CachedToken {
+    /// The token response.
+    pub token: TokenResponse,
+    /// The time when the access token expires.
+    #[serde(with = "unix_timestamp")]
+    pub expires_at: SystemTime,
+}
+
+impl CachedToken {
+    /// Wrap a [`TokenResponse`] just returned, and calculate its expiration time from now.
+    #[must_use]
+    pub fn new(token: TokenResponse) -> Self {
+        let expires_at = SystemTime::now() + Duration::from_secs(token.expires_in_secs);
+        Self { token, expires_at }
+    }
+
+    /// Get the remaining time before the access token expires, or `None` if it already expired.
+    #[must_use]
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires_at.duration_since(SystemTime::now()).ok()
+    }
+}
+
+impl fmt::Debug for CachedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedToken")
+            .field("token", &self.token)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+mod unix_timestamp {
+    use serde::Deserialize as _;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S: serde::Serializer>(
+        time: &SystemTime,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        serializer.serialize_u64(secs)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<SystemTime, D::Error> {
+        let secs = u64::deserialize(deserializer)?;
+        Ok(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+}
+
+/// A cache of the latest [`TokenResponse`] backed by a [`TokenStorage`].
+///
+/// When set via [`Auth::with_token_cache`][auth_token_cache], tokens returned by user logins,
+/// including rotated refresh tokens from [`Auth::login_with_refresh_token`][login_refresh],
+/// are persisted automatically.
+///
+/// [auth_token_cache]: ./struct.Auth.html#method.with_token_cache
+/// [login_refresh]: ./struct.Auth.html#method.login_with_refresh_token
+#[derive(Debug, Clone)]
+pub struct TokenCache {
+    storage: Arc<dyn TokenStorage>,
+    // Shared by clones, since `Auth` stores through its own clone.
+    store_error: Arc<Mutex<Option<Error>>>,
+}
+
+impl TokenCache {
+    /// Create a cache using `storage`.
+    pub fn new(storage: Arc<dyn TokenStorage>) -> Self {
+        Self {
+            storage,
+            store_error: Arc::default(),
+        }
+    }
+
+    /// Get the storage used to create this instance.
+    #[must_use]
+    pub fn storage(&self) -> &Arc<dyn TokenStorage> {
+        &self.storage
+    }
+
+    /// Load the cached token, or `None` if nothing is cached yet.
+    pub async fn load(&self) -> Result<Option<CachedToken>> {
+        match self.storage.load().await? {
+            None => Ok(None),
+            Some(data) => serde_json::from_str(&data)
+                .map(Some)
+                .map_err(|_| Error::unexpected_response("Invalid cached token")),
+        }
+    }
+
+    /// Cache a token response just returned.
+    pub async fn store(&self, token: &TokenResponse) -> Result<()> {
+        let data =
+            serde_json::to_string(&CachedToken::new(token.clone())).map_err(io::Error::from)?;
+        self.storage.store(&data).await
+    }
+
+    /// Store a token automatically, and keep the error on failure.
+    pub(crate) async fn store_or_keep_error(&self, token: &TokenResponse) {
+        if let Err(err) = self.store(token).await {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %err, "Failed to store token into cache");
+            *self.store_error.lock().unwrap() = Some(err);
+        }
+    }
+
+    /// Take the error of the last failed automatic store by [`Auth`][auth], if any.
+    ///
+    /// Logins succeed even if their tokens fail to be stored. Call this after logins to
+    /// detect it, eg. to warn users that they need to login again next time.
+    ///
+    /// [auth]: ./struct.Auth.html
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)] // The lock is never poisoned.
+    pub fn take_store_error(&self) -> Option<Error> {
+        self.store_error.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token() -> TokenResponse {
+        serde_json::from_value(serde_json::json!({
+            "token_type": "Bearer",
+            "scope": "files.read offline_access",
+            "expires_in": 3600,
+            "access_token": "some-access-token",
+            "refresh_token": "some-refresh-token",
+        }))
+        .unwrap()
+    }
+
+    async fn check_round_trip(storage: Arc<dyn TokenStorage>) {
+        let cache = TokenCache::new(storage);
+        assert!(cache.load().await.unwrap().is_none());
+
+        cache.store(&token()).await.unwrap();
+        let cached = cache.load().await.unwrap().unwrap();
+        assert_eq!(cached.token.scope, ["files.read", "offline_access"]);
+        assert_eq!(cached.token.access_token, "some-access-token");
+        assert_eq!(
+            cached.token.refresh_token.as_deref(),
+            Some("some-refresh-token"),
+        );
+        let remaining = cached.remaining().unwrap();
+        assert!(Duration::from_secs(3590) < remaining && remaining <= Duration::from_secs(3600));
+
+        let mut rotated = token();
+        rotated.refresh_token = Some("some-rotated-refresh-token".to_owned());
+        cache.store(&rotated).await.unwrap();
+        let cached = cache.load().await.unwrap().unwrap();
+        assert_eq!(
+            cached.token.refresh_token.as_deref(),
+            Some("some-rotated-refresh-token"),
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_storage() {
+        check_round_trip(Arc::new(MemoryTokenStorage::new())).await;
+    }
+
+    #[tokio::test]
+    async fn file_storage() {
+        let path = std::env::temp_dir().join(format!(
+            "onedrive-api-token-cache-test-{}.json",
+            std::process::id(),
+        ));
+        let _ = std::fs::remove_file(&path);
+        check_round_trip(Arc::new(FileTokenStorage::new(&path))).await;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
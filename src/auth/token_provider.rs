@@ -0,0 +1,264 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use super::{Auth, ClientCredential, TokenResponse};
+use crate::error::Result;
+use tokio::sync::Mutex;
+
+/// A source of access tokens, which is asked by [`OneDrive`][one_drive] before each request.
+///
+/// This allows long-running tasks to keep working after the initial access token expires.
+/// [`RefreshTokenProvider`] is a built-in implementation using refresh tokens.
+///
+/// # See also
+/// [`OneDrive::new_with_token_provider`][new_with_token_provider]
+///
+/// [one_drive]: ./struct.OneDrive.html
+/// [new_with_token_provider]: ./struct.OneDrive.html#method.new_with_token_provider
+pub trait TokenProvider: fmt::Debug + Send + Sync {
+    /// Get a currently valid access token.
+    fn access_token(&self) -> Pin<Box<dyn Future<Output = Result<String>> + Send + '_>>;
+
+    /// Get a new access token after `rejected_token` is rejected by the server with
+    /// `InvalidAuthenticationToken`, eg. revoked or expired earlier than expected.
+    ///
+    /// If `Some` is returned, the rejected request is replayed once with the new token.
+    /// The default implementation returns `None`, which means the token cannot be refreshed
+    /// and the error is returned as is.
+    fn refresh_rejected_token<'a>(
+        &'a self,
+        rejected_token: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<String>>> + Send + 'a>> {
+        let _ = rejected_token;
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// A [`TokenProvider`] that refreshes the access token by
+/// [`Auth::login_with_refresh_token`] when it is about to expire.
+///
+/// Concurrent requests share one in-flight refresh, and the rotated refresh token is kept
+/// for the next refresh.
+///
+/// # Example
+/// ```
+/// use onedrive_api::{
+///     Auth, ClientCredential, DriveLocation, OneDrive, Permission, RefreshTokenProvider, Tenant,
+/// };
+/// use std::sync::Arc;
+///
+/// # fn run(refresh_token: String) {
+/// let auth = Auth::new(
+///     "<...CLIENT ID...>",
+///     Permission::new_read().offline_access(true),
+///     "https://login.microsoftonline.com/common/oauth2/nativeclient",
+///     Tenant::Consumers,
+/// );
+/// let provider = RefreshTokenProvider::new(auth, refresh_token, ClientCredential::None);
+/// let drive = OneDrive::new_with_token_provider(Arc::new(provider), DriveLocation::me());
+/// # }
+/// ```
+pub struct RefreshTokenProvider {
+    auth: Auth,
+    client_credential: ClientCredential,
+    refresh_ahead: Duration,
+    state: Mutex<RefreshState>,
+}
+
+struct RefreshState {
+    refresh_token: String,
+    // The access token and its expiration time.
+    access_token: Option<(String, Instant)>,
+}
+
+impl RefreshTokenProvider {
+    /// The default duration before expiration to refresh the access token ahead.
+    pub const DEFAULT_REFRESH_AHEAD: Duration = Duration::from_secs(5 * 60);
+
+    /// Create a provider from a refresh token.
+    ///
+    /// No request is sent until the first access token is requested.
+    ///
+    /// # Panics
+    /// Refreshing will panic if `auth` is created with no
+    /// [`offline_access`][offline_access] permission.
+    /// See [`Auth::login_with_refresh_token`].
+    ///
+    /// [offline_access]: ./struct.Permission.html#method.offline_access
+    pub fn new(
+        auth: Auth,
+        refresh_token: impl Into<String>,
+        client_credential: ClientCredential,
+    ) -> Self {
+        Self {
+            auth,
+            client_credential,
+            refresh_ahead: Self::DEFAULT_REFRESH_AHEAD,
+            state: Mutex::new(RefreshState {
+                refresh_token: refresh_token.into(),
+                access_token: None,
+            }),
+        }
+    }
+
+    /// Create a provider from a [`TokenResponse`] just returned by a successful login.
+    ///
+    /// The access token in it is used until it is about to expire.
+    ///
+    /// # Panics
+    /// Panic if `token` has no [`refresh_token`][refresh_token].
+    ///
+    /// [refresh_token]: ./struct.TokenResponse.html#structfield.refresh_token
+    #[must_use]
+    pub fn from_token_response(
+        auth: Auth,
+        token: TokenResponse,
+        client_credential: ClientCredential,
+    ) -> Self {
+        let refresh_token = token
+            .refresh_token
+            .expect("Missing refresh token in TokenResponse");
+        let expire_at = Instant::now() + Duration::from_secs(token.expires_in_secs);
+        Self {
+            state: Mutex::new(RefreshState {
+                refresh_token,
+                access_token: Some((token.access_token, expire_at)),
+            }),
+            ..Self::new(auth, String::new(), client_credential)
+        }
+    }
+
+    /// Set how long before expiration the access token is refreshed.
+    ///
+    /// Default to be [`RefreshTokenProvider::DEFAULT_REFRESH_AHEAD`].
+    #[must_use]
+    pub fn refresh_ahead(mut self, refresh_ahead: Duration) -> Self {
+        self.refresh_ahead = refresh_ahead;
+        self
+    }
+
+    /// Get the `auth` used to create this instance.
+    #[must_use]
+    pub fn auth(&self) -> &Auth {
+        &self.auth
+    }
+
+    /// Get the latest refresh token.
+    ///
+    /// Refresh tokens are rotated on every refresh, so this may be different from the one used
+    /// to create this provider. It should be saved for future use.
+    pub async fn refresh_token(&self) -> String {
+        self.state.lock().await.refresh_token.clone()
+    }
+
+    async fn get_access_token(&self) -> Result<String> {
+        // Hold the lock during refreshing, so that concurrent callers wait for the same refresh.
+        let mut state = self.state.lock().await;
+        if let Some((token, expire_at)) = &state.access_token {
+            if Instant::now() + self.refresh_ahead < *expire_at {
+                return Ok(token.clone());
+            }
+        }
+        self.refresh(&mut state).await
+    }
+
+    async fn get_access_token_after_rejection(&self, rejected_token: &str) -> Result<String> {
+        let mut state = self.state.lock().await;
+        // Someone else already refreshed it.
+        if let Some((token, _)) = &state.access_token {
+            if token != rejected_token {
+                return Ok(token.clone());
+            }
+        }
+        self.refresh(&mut state).await
+    }
+
+    async fn refresh(&self, state: &mut RefreshState) -> Result<String> {
+        let resp = self
+            .auth
+            .login_with_refresh_token(&state.refresh_token, &self.client_credential)
+            .await?;
+        let expire_at = Instant::now() + Duration::from_secs(resp.expires_in_secs);
+        // Checked by `login_with_refresh_token`.
+        state.refresh_token = resp.refresh_token.unwrap();
+        state.access_token = Some((resp.access_token.clone(), expire_at));
+        Ok(resp.access_token)
+    }
+}
+
+impl fmt::Debug for RefreshTokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RefreshTokenProvider")
+            .field("auth", &self.auth)
+            .field("client_credential", &self.client_credential)
+            .field("refresh_ahead", &self.refresh_ahead)
+            // Skip tokens.
+            .finish_non_exhaustive()
+    }
+}
+
+impl TokenProvider for RefreshTokenProvider {
+    fn access_token(&self) -> Pin<Box<dyn Future<Output = Result<String>> + Send + '_>> {
+        Box::pin(self.get_access_token())
+    }
+
+    fn refresh_rejected_token<'a>(
+        &'a self,
+        rejected_token: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<String>>> + Send + 'a>> {
+        Box::pin(async move {
+            self.get_access_token_after_rejection(rejected_token)
+                .await
+                .map(Some)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Permission, Tenant};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[tokio::test]
+    async fn refresh_token_provider() {
+        assert_send_sync::<RefreshTokenProvider>();
+
+        let auth = Auth::new(
+            "some-client-id",
+            Permission::new_read().offline_access(true),
+            "http://example.com",
+            Tenant::Consumers,
+        );
+        let token: TokenResponse = serde_json::from_value(serde_json::json!({
+            "token_type": "Bearer",
+            "scope": "files.read",
+            "expires_in": 3600,
+            "access_token": "some-access-token",
+            "refresh_token": "some-refresh-token",
+        }))
+        .unwrap();
+        let provider =
+            RefreshTokenProvider::from_token_response(auth, token, ClientCredential::None);
+        let debug = format!("{provider:?}");
+        assert!(!debug.contains("some-access-token") && !debug.contains("some-refresh-token"));
+
+        // Still valid, no request is sent.
+        assert_eq!(provider.access_token().await.unwrap(), "some-access-token");
+        assert_eq!(provider.refresh_token().await, "some-refresh-token");
+        // Already refreshed by others, no request is sent.
+        assert_eq!(
+            provider
+                .refresh_rejected_token("some-old-access-token")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("some-access-token"),
+        );
+    }
+}
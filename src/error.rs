@@ -1,7 +1,7 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use crate::resource::{ErrorResponse, OAuth2ErrorResponse};
-use reqwest::StatusCode;
+use crate::resource::{ErrorCode, ErrorResponse, OAuth2ErrorKind, OAuth2ErrorResponse};
+use reqwest::{header::HeaderMap, StatusCode};
 use thiserror::Error;
 
 /// An alias to `Result` of [`Error`][error].
@@ -10,10 +10,12 @@ use thiserror::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Error of API request
-#[derive(Debug, Error)]
+///
+/// It is cheap to clone, and clones share the same underlying error.
+#[derive(Debug, Clone, Error)]
 #[error(transparent)]
 pub struct Error {
-    inner: Box<ErrorKind>,
+    inner: Arc<ErrorKind>,
 }
 
 #[derive(Debug, Error)]
@@ -21,6 +23,15 @@ enum ErrorKind {
     // Errors about ser/de are included.
     #[error("Request error: {0}")]
     RequestError(#[source] reqwest::Error),
+    // Error status without a JSON body, eg. from pre-authenticated URLs.
+    #[error("Request error: {source}")]
+    StatusError {
+        #[source]
+        source: reqwest::Error,
+        diagnostics: ResponseDiagnostics,
+    },
+    #[error("IO error: {0}")]
+    IoError(#[source] std::io::Error),
     #[error("Unexpected response: {reason}")]
     UnexpectedResponse { reason: &'static str },
     #[error("Api error with {status}: ({}) {}", .response.code, .response.message)]
@@ -28,6 +39,7 @@ enum ErrorKind {
         status: StatusCode,
         response: ErrorResponse,
         retry_after: Option<u32>,
+        diagnostics: ResponseDiagnostics,
     },
     #[error("OAuth2 error with {status}: ({}) {}", .response.error, .response.error_description)]
     OAuth2Error {
@@ -35,6 +47,8 @@ enum ErrorKind {
         response: OAuth2ErrorResponse,
         retry_after: Option<u32>,
     },
+    #[error("OAuth2 error in redirect: ({}) {}", .response.error, .response.error_description)]
+    OAuth2RedirectError { response: OAuth2ErrorResponse },
 }
 
 impl Error {
@@ -42,29 +56,54 @@ impl Error {
         status: StatusCode,
         response: ErrorResponse,
         retry_after: Option<u32>,
+        diagnostics: ResponseDiagnostics,
     ) -> Self {
         Self {
-            inner: Box::new(ErrorKind::ErrorResponse {
+            inner: Arc::new(ErrorKind::ErrorResponse {
                 status,
                 response,
                 retry_after,
+                diagnostics,
+            }),
+        }
+    }
+
+    pub(crate) fn from_status_error(
+        source: reqwest::Error,
+        diagnostics: ResponseDiagnostics,
+    ) -> Self {
+        Self {
+            inner: Arc::new(ErrorKind::StatusError {
+                source,
+                diagnostics,
             }),
         }
     }
 
     pub(crate) fn unexpected_response(reason: &'static str) -> Self {
         Self {
-            inner: Box::new(ErrorKind::UnexpectedResponse { reason }),
+            inner: Arc::new(ErrorKind::UnexpectedResponse { reason }),
         }
     }
 
+    /// An error detected locally as if the server responded `412 Precondition Failed` with
+    /// `resourceModified`.
+    pub(crate) fn resource_modified(message: &str, diagnostics: ResponseDiagnostics) -> Self {
+        let response = ErrorResponse {
+            code: ErrorCode::ResourceModified.as_str().to_owned(),
+            message: message.to_owned(),
+            inner_error: None,
+        };
+        Self::from_error_response(StatusCode::PRECONDITION_FAILED, response, None, diagnostics)
+    }
+
     pub(crate) fn from_oauth2_error_response(
         status: StatusCode,
         response: OAuth2ErrorResponse,
         retry_after: Option<u32>,
     ) -> Self {
         Self {
-            inner: Box::new(ErrorKind::OAuth2Error {
+            inner: Arc::new(ErrorKind::OAuth2Error {
                 status,
                 response,
                 retry_after,
@@ -72,6 +111,12 @@ impl Error {
         }
     }
 
+    pub(crate) fn from_oauth2_redirect_error(response: OAuth2ErrorResponse) -> Self {
+        Self {
+            inner: Arc::new(ErrorKind::OAuth2RedirectError { response }),
+        }
+    }
+
     /// Get the error response from API if caused by error status code.
     #[must_use]
     pub fn error_response(&self) -> Option<&ErrorResponse> {
@@ -81,21 +126,146 @@ impl Error {
         }
     }
 
+    /// Get the diagnostic information from response headers if caused by error response from
+    /// API, which is usually required by Microsoft support.
+    ///
+    /// This is also available for error responses without a JSON body.
+    #[must_use]
+    pub fn diagnostics(&self) -> Option<&ResponseDiagnostics> {
+        match &*self.inner {
+            ErrorKind::ErrorResponse { diagnostics, .. }
+            | ErrorKind::StatusError { diagnostics, .. } => Some(diagnostics),
+            _ => None,
+        }
+    }
+
     /// Get the OAuth2 error response from API if caused by OAuth2 error response.
+    ///
+    /// This includes errors parsed from redirect URL by
+    /// [`CodeAuthRequest::parse_redirect_url`][parse_redirect_url], which has no status code.
+    ///
+    /// [parse_redirect_url]: ./struct.CodeAuthRequest.html#method.parse_redirect_url
     #[must_use]
     pub fn oauth2_error_response(&self) -> Option<&OAuth2ErrorResponse> {
         match &*self.inner {
-            ErrorKind::OAuth2Error { response, .. } => Some(response),
+            ErrorKind::OAuth2Error { response, .. }
+            | ErrorKind::OAuth2RedirectError { response } => Some(response),
             _ => None,
         }
     }
 
+    /// Get the classification of the OAuth2 error response, if caused by OAuth2 error response.
+    ///
+    /// This is a shortcut to [`OAuth2ErrorResponse::kind`] of
+    /// [`oauth2_error_response`][oauth2_error_response].
+    ///
+    /// [oauth2_error_response]: #method.oauth2_error_response
+    #[must_use]
+    pub fn oauth2_error_kind(&self) -> Option<OAuth2ErrorKind> {
+        self.oauth2_error_response().map(OAuth2ErrorResponse::kind)
+    }
+
+    /// Get the typed top-level error code if caused by error response from API.
+    ///
+    /// This is a shortcut to [`ErrorResponse::error_code`] of
+    /// [`error_response`][error_response].
+    ///
+    /// [error_response]: #method.error_response
+    #[must_use]
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        self.error_response().map(ErrorResponse::error_code)
+    }
+
+    /// Check if `code` is the top-level code or any of nested inner error codes of the error
+    /// response from API.
+    ///
+    /// # See also
+    /// [`ErrorResponse::has_error_code`]
+    #[must_use]
+    pub fn has_error_code(&self, code: ErrorCode) -> bool {
+        self.error_response()
+            .is_some_and(|resp| resp.has_error_code(code))
+    }
+
+    /// Check if the request may succeed when retried later, eg. on throttling (HTTP 429),
+    /// server errors (HTTP 5xx except 501 and 507), timeouts, connection failures or resets.
+    ///
+    /// This does not consider whether the request is idempotent. Failures while reading the
+    /// response body, eg. connection resets after the response headers are received, are not
+    /// retryable, since the response may be partially consumed.
+    /// Use [`retry_after`][retry_after] to get the delay hint, if any.
+    ///
+    /// [retry_after]: #method.retry_after
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        let retryable_status = |status: StatusCode| {
+            status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS
+                || (status.is_server_error()
+                    && status != StatusCode::NOT_IMPLEMENTED
+                    && status != StatusCode::INSUFFICIENT_STORAGE)
+        };
+        match &*self.inner {
+            ErrorKind::RequestError(source) | ErrorKind::StatusError { source, .. } => {
+                self.is_connection_error() || source.status().is_some_and(retryable_status)
+            }
+            ErrorKind::IoError(_)
+            | ErrorKind::UnexpectedResponse { .. }
+            | ErrorKind::OAuth2RedirectError { .. } => false,
+            ErrorKind::ErrorResponse {
+                status, response, ..
+            } => {
+                retryable_status(*status)
+                    || response.has_error_code(ErrorCode::ActivityLimitReached)
+                    || response.has_error_code(ErrorCode::ServiceNotAvailable)
+            }
+            ErrorKind::OAuth2Error {
+                status, response, ..
+            } => retryable_status(*status) || response.error == "temporarily_unavailable",
+        }
+    }
+
+    /// Check if the request conflicts with the current state of the resource, eg. the item name
+    /// already exists (HTTP 409), or the eTag does not match (HTTP 412).
+    #[must_use]
+    pub fn is_conflict(&self) -> bool {
+        matches!(
+            self.status_code(),
+            Some(StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED)
+        ) || self.has_error_code(ErrorCode::NameAlreadyExists)
+            || self.has_error_code(ErrorCode::ResourceModified)
+    }
+
+    /// Check if the request failed to be sent or the response headers failed to be received, eg.
+    /// connection failures, resets and timeouts.
+    ///
+    /// Failures while reading the response body (`is_body` and `is_decode`) are excluded.
+    pub(crate) fn is_connection_error(&self) -> bool {
+        match &*self.inner {
+            ErrorKind::RequestError(source) => {
+                source.is_connect() || source.is_timeout() || source.is_request()
+            }
+            _ => false,
+        }
+    }
+
+    /// Check if the requested resource is not found (HTTP 404 or `itemNotFound`).
+    #[must_use]
+    pub fn is_not_found(&self) -> bool {
+        self.status_code() == Some(StatusCode::NOT_FOUND)
+            || self.has_error_code(ErrorCode::ItemNotFound)
+    }
+
     /// Get the HTTP status code if caused by error status code.
     #[must_use]
     pub fn status_code(&self) -> Option<StatusCode> {
         match &*self.inner {
-            ErrorKind::RequestError(source) => source.status(),
-            ErrorKind::UnexpectedResponse { .. } => None,
+            ErrorKind::RequestError(source) | ErrorKind::StatusError { source, .. } => {
+                source.status()
+            }
+            ErrorKind::IoError(_)
+            | ErrorKind::UnexpectedResponse { .. }
+            | ErrorKind::OAuth2RedirectError { .. } => None,
             ErrorKind::ErrorResponse { status, .. } | ErrorKind::OAuth2Error { status, .. } => {
                 Some(*status)
             }
@@ -118,10 +288,47 @@ impl Error {
     }
 }
 
+/// Diagnostic information from headers of an error response of Microsoft Graph.
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/best-practices-concept#reliability-and-support)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResponseDiagnostics {
+    /// `request-id`, the unique identifier of the request assigned by the server.
+    pub request_id: Option<String>,
+    /// `client-request-id`, which is the one set in the request if any.
+    pub client_request_id: Option<String>,
+    /// `x-ms-ags-diagnostic`, a JSON object about the data center serving the request.
+    pub ags_diagnostic: Option<String>,
+    /// `Date`, the time when the response is sent, in HTTP date format.
+    pub date: Option<String>,
+}
+
+impl ResponseDiagnostics {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name: &str| Some(headers.get(name)?.to_str().ok()?.to_owned());
+        Self {
+            request_id: get("request-id"),
+            client_request_id: get("client-request-id"),
+            ags_diagnostic: get("x-ms-ags-diagnostic"),
+            date: get("date"),
+        }
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(source: reqwest::Error) -> Self {
         Self {
-            inner: Box::new(ErrorKind::RequestError(source)),
+            inner: Arc::new(ErrorKind::RequestError(source)),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(source: std::io::Error) -> Self {
+        Self {
+            inner: Arc::new(ErrorKind::IoError(source)),
         }
     }
 }
@@ -139,4 +346,42 @@ mod tests {
         let source_err_fmt = Error::from(err).source().unwrap().to_string();
         assert_eq!(source_err_fmt, original_err_fmt);
     }
+
+    #[test]
+    fn error_queries() {
+        let err = |status: u16, code: &str| {
+            let response = serde_json::from_value(serde_json::json!({
+                "code": code,
+                "message": "Some message",
+            }))
+            .unwrap();
+            Error::from_error_response(
+                StatusCode::from_u16(status).unwrap(),
+                response,
+                None,
+                ResponseDiagnostics::default(),
+            )
+        };
+
+        let e = err(404, "itemNotFound");
+        assert_eq!(e.error_code(), Some(ErrorCode::ItemNotFound));
+        assert!(e.is_not_found());
+        assert!(!e.is_retryable());
+        assert!(!e.is_conflict());
+
+        let e = err(409, "nameAlreadyExists");
+        assert!(e.is_conflict());
+        assert!(!e.is_retryable());
+        assert!(err(412, "resourceModified").is_conflict());
+
+        assert!(err(429, "activityLimitReached").is_retryable());
+        assert!(err(503, "serviceNotAvailable").is_retryable());
+        assert!(err(500, "generalException").is_retryable());
+        let e = err(507, "quotaLimitReached");
+        assert!(!e.is_retryable() && !e.is_conflict());
+
+        let e = Error::unexpected_response("test");
+        assert_eq!(e.error_code(), None);
+        assert!(!e.is_retryable() && !e.is_conflict() && !e.is_not_found());
+    }
 }
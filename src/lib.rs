@@ -48,10 +48,24 @@
 //!
 //!   Be carefully using it and **do NOT use it in production**.
 //!
+//! - `client-assertion`
+//!
+//!   Enable [`ClientAssertion`][client_assertion] to build signed JWT client assertions from a
+//!   certificate private key, for confidential clients.
+//!
+//! - `tracing`
+//!
+//!   Record a [`tracing`][tracing] span for each operation of [`OneDrive`][one_drive], with
+//!   events for each request, including its HTTP status, `request-id`, `client-request-id` and
+//!   retry count. Access tokens, request bodies and query strings are never recorded, and
+//!   pre-authenticated URLs are reduced to their origins.
+//!
 //! [ms_onedrive]: https://products.office.com/en-us/onedrive/online-cloud-storage
 //! [ms_graph]: https://docs.microsoft.com/graph/overview
 //! [one_drive]: ./struct.OneDrive.html
 //! [auth]: ./struct.Auth.html
+//! [client_assertion]: ./struct.ClientAssertion.html
+//! [tracing]: https://docs.rs/tracing
 //! [api]: ./trait.Api.html
 //! [api_execute]: ./trait.Api.html#tymethod.execute
 //! [client]: ./trait.Client.html
@@ -59,23 +73,38 @@
 #![deny(missing_debug_implementations)]
 #![deny(missing_docs)]
 use serde::{de, Serialize};
+use std::ops::{Range, RangeFrom};
 
 mod auth;
 mod error;
 mod onedrive;
 pub mod option;
+mod rate_limit;
 pub mod resource;
+mod retry;
+#[cfg(feature = "tracing")]
+mod trace;
 mod util;
 
+#[cfg(feature = "client-assertion")]
+pub use self::auth::ClientAssertion;
 pub use self::{
-    auth::{Auth, ClientCredential, Permission, Tenant, TokenResponse},
-    error::{Error, Result},
+    auth::{
+        Auth, CachedToken, ClientCredential, CodeAuthRequest, DeviceCodeResponse, FileTokenStorage,
+        LoopbackRedirectListener, MemoryTokenStorage, Permission, PkceCodeVerifier,
+        RefreshTokenProvider, Scope, ScopeSet, Tenant, TokenCache, TokenProvider, TokenResponse,
+        TokenStorage,
+    },
+    error::{Error, ResponseDiagnostics, Result},
     onedrive::{
-        CopyProgressMonitor, ListChildrenFetcher, OneDrive, TrackChangeFetcher, UploadSession,
+        BatchHandle, BatchRequest, BatchResponse, CopyProgressMonitor, ListChildrenFetcher,
+        OneDrive, RangedDownload, SegmentedDownload, TrackChangeFetcher, UploadSession,
         UploadSessionMeta,
     },
+    rate_limit::{RateLimitPermit, RateLimiter},
     resource::{DriveId, ItemId, Tag},
-    util::{DriveLocation, FileName, ItemLocation},
+    retry::RetryPolicy,
+    util::{Cloud, DriveLocation, FileName, ItemLocation},
 };
 
 #[cfg(feature = "beta")]
@@ -98,7 +127,31 @@ pub enum ConflictBehavior {
     Rename,
 }
 
+/// The target format to convert a file into on download.
+///
+/// Only some source file types can be converted into each format. Downloading other files
+/// results in `Err` with HTTP 406 NOT ACCEPTABLE or `notSupported`.
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/api/driveitem-get-content-format?view=graph-rest-1.0)
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ConvertFormat {
+    /// PDF, from Office documents and some other files, eg. `docx`, `xlsx`, `pptx`, `rtf`,
+    /// `odt`, `eml` and `html`.
+    Pdf,
+    /// HTML, from Loop and Fluid components and Whiteboard, eg. `loop`, `fluid` and `wbtx`.
+    Html,
+    /// GLB, from 3D models, eg. `cool`, `fbx`, `obj`, `ply`, `stl` and `3mf`.
+    Glb,
+    /// JPEG, from images and some other files, eg. `png`, `heic`, `tiff`, `psd` and `ai`.
+    Jpg,
+}
+
 /// A half-open byte range `start..end` or `start..`.
+///
+/// It can be converted from `Range<u64>` and `RangeFrom<u64>`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ExpectRange {
     /// The lower bound of the range (inclusive).
@@ -107,6 +160,24 @@ pub struct ExpectRange {
     pub end: Option<u64>,
 }
 
+impl From<Range<u64>> for ExpectRange {
+    fn from(range: Range<u64>) -> Self {
+        Self {
+            start: range.start,
+            end: Some(range.end),
+        }
+    }
+}
+
+impl From<RangeFrom<u64>> for ExpectRange {
+    fn from(range: RangeFrom<u64>) -> Self {
+        Self {
+            start: range.start,
+            end: None,
+        }
+    }
+}
+
 impl<'de> de::Deserialize<'de> for ExpectRange {
     fn deserialize<D: de::Deserializer<'de>>(
         deserializer: D,
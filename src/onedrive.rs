@@ -1,24 +1,34 @@
 #![allow(clippy::default_trait_access)] // Forwarding default options is allowed.
+use self::download::{part_path, range_header, same_e_tag, write_response, write_response_to_file};
 use crate::{
-    error::{Error, Result},
+    auth::TokenProvider,
+    error::{Error, ResponseDiagnostics, Result},
     option::{CollectionOption, DriveItemPutOption, ObjectOption},
-    resource::{Drive, DriveField, DriveItem, DriveItemField, TimestampString},
+    rate_limit::RateLimiter,
+    resource::{Drive, DriveField, DriveItem, DriveItemField, ErrorCode, Tag, TimestampString},
+    retry::RetryPolicy,
     util::{
-        handle_error_response, ApiPathComponent, DriveLocation, FileName, ItemLocation,
-        RequestBuilderExt as _, ResponseExt as _,
+        handle_error_response, ApiPathComponent, Cloud, DriveLocation, FileName, ItemLocation,
+        RequestBuilderExt as _, RequestBuilderTransformer, ResponseExt as _,
     },
-    {ConflictBehavior, ExpectRange},
+    {ConflictBehavior, ConvertFormat, ExpectRange},
 };
 use bytes::Bytes;
-use reqwest::{header, Client};
+use futures_core::Stream;
+use futures_util::TryStreamExt as _;
+use reqwest::{header, Client, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::fmt;
+use std::{fmt, path::Path, sync::Arc};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite};
 use url::Url;
 
+#[cfg(feature = "tracing")]
+use crate::trace;
+
 macro_rules! api_url {
-    ($($seg:expr),* $(,)?) => {{
-        let mut url = Url::parse("https://graph.microsoft.com/v1.0").unwrap();
+    ($base:expr; $($seg:expr),* $(,)?) => {{
+        let mut url: Url = $base.clone();
         {
             let mut buf = url.path_segments_mut().unwrap();
             $(ApiPathComponent::extend_into($seg, &mut buf);)*
@@ -38,12 +48,30 @@ macro_rules! api_path {
     .path()};
 }
 
+mod batch;
+mod download;
+
+pub use self::{
+    batch::{BatchHandle, BatchRequest, BatchResponse},
+    download::{RangedDownload, SegmentedDownload},
+};
+
 /// The authorized client to access OneDrive resources in a specified Drive.
 #[derive(Clone)]
 pub struct OneDrive {
     client: Client,
-    token: String,
+    token: AccessToken,
     drive: DriveLocation,
+    api_base: Url,
+    retry_policy: Option<RetryPolicy>,
+    rate_limiter: Option<RateLimiter>,
+    client_request_id: Option<Arc<dyn Fn() -> String + Send + Sync>>,
+}
+
+#[derive(Clone)]
+enum AccessToken {
+    Fixed(String),
+    Provider(Arc<dyn TokenProvider>),
 }
 
 impl fmt::Debug for OneDrive {
@@ -52,6 +80,10 @@ impl fmt::Debug for OneDrive {
             .field("client", &self.client)
             // Skip `token`.
             .field("drive", &self.drive)
+            .field("api_base", &self.api_base)
+            .field("retry_policy", &self.retry_policy)
+            .field("rate_limiter", &self.rate_limiter)
+            // Skip `client_request_id`.
             .finish_non_exhaustive()
     }
 }
@@ -62,12 +94,7 @@ impl OneDrive {
     /// # Panics
     /// It panics if the underlying `reqwest::Client` cannot be created.
     pub fn new(access_token: impl Into<String>, drive: impl Into<DriveLocation>) -> Self {
-        let client = Client::builder()
-            .redirect(reqwest::redirect::Policy::none())
-            .gzip(true)
-            .build()
-            .unwrap();
-        Self::new_with_client(client, access_token, drive.into())
+        Self::new_with_client(default_client(), access_token, drive.into())
     }
 
     /// Same as [`OneDrive::new`] but with custom `reqwest::Client`.
@@ -86,9 +113,150 @@ impl OneDrive {
     ) -> Self {
         OneDrive {
             client,
-            token: access_token.into(),
+            token: AccessToken::Fixed(access_token.into()),
             drive: drive.into(),
+            api_base: Cloud::default().graph_api_url(),
+            retry_policy: None,
+            rate_limiter: None,
+            client_request_id: None,
+        }
+    }
+
+    /// Create a new OneDrive instance which asks `token_provider` for an access token before
+    /// each request, to perform operations in a Drive.
+    ///
+    /// # Panics
+    /// It panics if the underlying `reqwest::Client` cannot be created.
+    pub fn new_with_token_provider(
+        token_provider: Arc<dyn TokenProvider>,
+        drive: impl Into<DriveLocation>,
+    ) -> Self {
+        Self::new_with_client_and_token_provider(default_client(), token_provider, drive)
+    }
+
+    /// Same as [`OneDrive::new_with_token_provider`] but with custom `reqwest::Client`.
+    ///
+    /// # Note
+    /// The given `client` should have redirection disabled.
+    /// See also the docs of [`OneDrive::new_with_client`].
+    ///
+    /// [`OneDrive::new_with_token_provider`]: #method.new_with_token_provider
+    /// [`OneDrive::new_with_client`]: #method.new_with_client
+    pub fn new_with_client_and_token_provider(
+        client: Client,
+        token_provider: Arc<dyn TokenProvider>,
+        drive: impl Into<DriveLocation>,
+    ) -> Self {
+        OneDrive {
+            client,
+            token: AccessToken::Provider(token_provider),
+            drive: drive.into(),
+            api_base: Cloud::default().graph_api_url(),
+            retry_policy: None,
+            rate_limiter: None,
+            client_request_id: None,
+        }
+    }
+
+    /// Set the cloud environment, which determines the Microsoft Graph host to send requests to.
+    ///
+    /// Default to be [`Cloud::global`]. It should match the [`Cloud`] of the [`Auth`][auth]
+    /// which the access token is from.
+    ///
+    /// [auth]: ./struct.Auth.html
+    #[must_use]
+    pub fn with_cloud(self, cloud: &Cloud) -> Self {
+        self.with_base_url(cloud.graph_api_url())
+    }
+
+    /// Set the base URL of Microsoft Graph API, eg. `https://graph.microsoft.com/v1.0`, which
+    /// all endpoints are relative to.
+    ///
+    /// This is mainly for testing against a local mock server, eg. `http://127.0.0.1:8080`.
+    /// Use [`OneDrive::with_cloud`] for national clouds instead.
+    ///
+    /// A trailing slash of `base_url` is ignored.
+    ///
+    /// [`OneDrive::with_cloud`]: #method.with_cloud
+    #[must_use]
+    pub fn with_base_url(mut self, mut base_url: Url) -> Self {
+        if let Ok(mut segments) = base_url.path_segments_mut() {
+            segments.pop_if_empty();
         }
+        self.api_base = base_url;
+        self
+    }
+
+    /// Get the base URL of Microsoft Graph API used by this instance.
+    #[must_use]
+    pub fn base_url(&self) -> &Url {
+        &self.api_base
+    }
+
+    /// Set the policy to retry failed requests, eg. on throttling.
+    ///
+    /// Default to be `None`, where requests are never retried. This also applies to requests of
+    /// fetchers using this instance, but not [`UploadSession`][upload_session]. It can be
+    /// overridden per call by the `retry_policy` of options, eg.
+    /// [`ObjectOption::retry_policy`][retry_policy].
+    ///
+    /// [upload_session]: ./struct.UploadSession.html
+    /// [retry_policy]: ./option/struct.ObjectOption.html#method.retry_policy
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry_policy: impl Into<Option<RetryPolicy>>) -> Self {
+        self.retry_policy = retry_policy.into();
+        self
+    }
+
+    /// Get the retry policy of this instance, if any.
+    #[must_use]
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    /// Set the limiter of in-flight requests and request rate, which can be shared by multiple
+    /// instances.
+    ///
+    /// Default to be `None`, where requests are not limited. This also applies to requests of
+    /// fetchers and [`upload_with_option`][upload] using this instance, but not methods of
+    /// [`UploadSession`][upload_session]. Each retry under the [`RetryPolicy`] acquires a new
+    /// permit. A permit is released when the response headers are received, before the body
+    /// is read.
+    ///
+    /// [upload]: #method.upload_with_option
+    ///
+    /// [upload_session]: ./struct.UploadSession.html
+    #[must_use]
+    pub fn with_rate_limiter(mut self, rate_limiter: impl Into<Option<RateLimiter>>) -> Self {
+        self.rate_limiter = rate_limiter.into();
+        self
+    }
+
+    /// Get the rate limiter of this instance, if any.
+    #[must_use]
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Set the generator of `client-request-id` for operations without one set by options, eg.
+    /// a random GUID, to correlate requests with logs of Microsoft Graph.
+    ///
+    /// Default to be `None`, where `client-request-id` is only sent if set by options. This
+    /// applies to all requests sent by this instance, including fetchers, batches and parts of
+    /// [`upload_with_option`][upload], but not methods of [`UploadSession`] called directly.
+    /// Retries of an operation share the same `client-request-id`.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/best-practices-concept#reliability-and-support)
+    ///
+    /// [upload]: #method.upload_with_option
+    #[must_use]
+    pub fn with_client_request_id_generator(
+        mut self,
+        generator: impl Fn() -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.client_request_id = Some(Arc::new(generator));
+        self
     }
 
     /// Get the `reqwest::Client` used to create the OneDrive instance.
@@ -98,9 +266,162 @@ impl OneDrive {
     }
 
     /// Get the access token used to create the OneDrive instance.
+    ///
+    /// Return `None` if the instance is created with a [`TokenProvider`].
+    #[must_use]
+    pub fn access_token(&self) -> Option<&str> {
+        match &self.token {
+            AccessToken::Fixed(token) => Some(token),
+            AccessToken::Provider(_) => None,
+        }
+    }
+
+    /// Get the token provider used to create the OneDrive instance, if any.
     #[must_use]
-    pub fn access_token(&self) -> &str {
-        &self.token
+    pub fn token_provider(&self) -> Option<&Arc<dyn TokenProvider>> {
+        match &self.token {
+            AccessToken::Fixed(_) => None,
+            AccessToken::Provider(provider) => Some(provider),
+        }
+    }
+
+    /// Send an authorized request, and retry it under the retry policy, if any.
+    ///
+    /// `op` is the name of the operation recorded with feature `tracing`.
+    async fn send(&self, op: &'static str, req: RequestBuilder) -> Result<Response> {
+        self.send_with_policy(op, OpContext::default(), req).await
+    }
+
+    /// Send an authorized request operating on `item`.
+    async fn send_item(
+        &self,
+        op: &'static str,
+        item: &ItemLocation<'_>,
+        req: RequestBuilder,
+    ) -> Result<Response> {
+        let ctx = OpContext {
+            item: Some(item),
+            ..OpContext::default()
+        };
+        self.send_with_policy(op, ctx, req).await
+    }
+
+    /// Send an authorized request with `option` applied, operating on `item` if any.
+    ///
+    /// The retry policy of `option`, if any, overrides the one of this instance.
+    async fn send_with_option(
+        &self,
+        op: &'static str,
+        item: Option<&ItemLocation<'_>>,
+        req: RequestBuilder,
+        option: impl RequestBuilderTransformer,
+    ) -> Result<Response> {
+        let retry_policy = option.get_retry_policy().cloned();
+        let ctx = OpContext {
+            item,
+            retry_policy: retry_policy.as_ref(),
+            ..OpContext::default()
+        };
+        self.send_with_policy(op, ctx, req.apply(option)).await
+    }
+
+    /// Send a request under the retry policy and the rate limiter, if any.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    async fn send_with_policy(
+        &self,
+        op: &'static str,
+        ctx: OpContext<'_, '_>,
+        req: RequestBuilder,
+    ) -> Result<Response> {
+        let authorized = ctx.authorized;
+        let fut = async {
+            let req = match &self.client_request_id {
+                Some(generator) => with_default_client_request_id(req, &**generator)?,
+                None => req,
+            };
+            let retry_policy = ctx.retry_policy.or(self.retry_policy.as_ref());
+            match retry_policy.filter(|_| ctx.retry) {
+                None => self.send_limited(req, authorized).await,
+                Some(policy) => {
+                    policy
+                        .send(req, |req| self.send_limited(req, authorized))
+                        .await
+                }
+            }
+        };
+        #[cfg(feature = "tracing")]
+        let fut = tracing::Instrument::instrument(
+            fut,
+            trace::span(
+                op,
+                Some(&self.drive).filter(|_| authorized),
+                ctx.item,
+                ctx.page,
+            ),
+        );
+        fut.await
+    }
+
+    /// Send a request under the rate limiter, if any.
+    async fn send_limited(&self, req: RequestBuilder, authorized: bool) -> Result<Response> {
+        let send = |req| async move {
+            if authorized {
+                self.send_once(req).await
+            } else {
+                self.send_raw(req).await
+            }
+        };
+        let Some(limiter) = &self.rate_limiter else {
+            return send(req).await;
+        };
+        let _permit = limiter.acquire().await;
+        let resp = send(req).await?;
+        limiter.observe(&resp);
+        Ok(resp)
+    }
+
+    /// Send an authorized request.
+    ///
+    /// If the token is from a [`TokenProvider`] and it is rejected with
+    /// `InvalidAuthenticationToken`, the token is refreshed and the request is replayed once.
+    async fn send_once(&self, req: RequestBuilder) -> Result<Response> {
+        let provider = match &self.token {
+            AccessToken::Fixed(token) => return self.send_raw(req.bearer_auth(token)).await,
+            AccessToken::Provider(provider) => provider,
+        };
+
+        let token = provider.access_token().await?;
+        let retry_req = req.try_clone();
+        let resp = self.send_raw(req.bearer_auth(&token)).await?;
+        let Some(retry_req) = retry_req.filter(|_| resp.status() == StatusCode::UNAUTHORIZED)
+        else {
+            return Ok(resp);
+        };
+
+        let err = handle_error_response(resp).await.unwrap_err();
+        if err.error_code() != Some(ErrorCode::InvalidAuthenticationToken) {
+            return Err(err);
+        }
+        match provider.refresh_rejected_token(&token).await? {
+            Some(token) => self.send_raw(retry_req.bearer_auth(token)).await,
+            None => Err(err),
+        }
+    }
+
+    /// Send a request as is, and record the result with feature `tracing`.
+    async fn send_raw(&self, req: RequestBuilder) -> Result<Response> {
+        let ret = req.send().await;
+        #[cfg(feature = "tracing")]
+        trace::record_response(&ret, Some(&self.api_base));
+        Ok(ret?)
+    }
+
+    /// Create a builder of JSON batch requests, to combine multiple operations into one request.
+    ///
+    /// See [`BatchRequest`] for more details.
+    #[must_use]
+    pub fn batch(&self) -> BatchRequest<'_> {
+        BatchRequest::new(self)
     }
 
     /// Get current `Drive`.
@@ -112,14 +433,15 @@ impl OneDrive {
     ///
     /// [drive]: ./resource/struct.Drive.html
     pub async fn get_drive_with_option(&self, option: ObjectOption<DriveField>) -> Result<Drive> {
-        self.client
-            .get(api_url![&self.drive])
-            .apply(option)
-            .bearer_auth(&self.token)
-            .send()
-            .await?
-            .parse()
-            .await
+        self.send_with_option(
+            "get_drive_with_option",
+            None,
+            self.client.get(api_url![self.api_base; &self.drive]),
+            option,
+        )
+        .await?
+        .parse()
+        .await
     }
 
     /// Shortcut to `get_drive_with_option` with default parameters.
@@ -154,12 +476,15 @@ impl OneDrive {
         item: impl Into<ItemLocation<'a>>,
         option: CollectionOption<DriveItemField>,
     ) -> Result<Option<ListChildrenFetcher>> {
+        let item = item.into();
         let opt_resp = self
-            .client
-            .get(api_url![&self.drive, &item.into(), "children"])
-            .apply(option)
-            .bearer_auth(&self.token)
-            .send()
+            .send_with_option(
+                "list_children_with_option",
+                Some(&item),
+                self.client
+                    .get(api_url![self.api_base; &self.drive, &item, "children"]),
+                option,
+            )
             .await?
             .parse_optional()
             .await?;
@@ -202,14 +527,16 @@ impl OneDrive {
         item: impl Into<ItemLocation<'a>>,
         option: ObjectOption<DriveItemField>,
     ) -> Result<Option<DriveItem>> {
-        self.client
-            .get(api_url![&self.drive, &item.into()])
-            .apply(option)
-            .bearer_auth(&self.token)
-            .send()
-            .await?
-            .parse_optional()
-            .await
+        let item = item.into();
+        self.send_with_option(
+            "get_item_with_option",
+            Some(&item),
+            self.client.get(api_url![self.api_base; &self.drive, &item]),
+            option,
+        )
+        .await?
+        .parse_optional()
+        .await
     }
 
     /// Shortcut to `get_item_with_option` with default parameters.
@@ -246,12 +573,15 @@ impl OneDrive {
         item: impl Into<ItemLocation<'a>>,
         option: ObjectOption<DriveItemField>,
     ) -> Result<String> {
+        let item = item.into();
         let raw_resp = self
-            .client
-            .get(api_url![&self.drive, &item.into(), "content"])
-            .apply(option)
-            .bearer_auth(&self.token)
-            .send()
+            .send_with_option(
+                "get_item_download_url_with_option",
+                Some(&item),
+                self.client
+                    .get(api_url![self.api_base; &self.drive, &item, "content"]),
+                option,
+            )
             .await?;
         let url = handle_error_response(raw_resp)
             .await?
@@ -282,6 +612,501 @@ impl OneDrive {
             .await
     }
 
+    /// Download the content of a file as a stream of bytes.
+    ///
+    /// The redirection to the pre-authorized download URL is followed internally, without
+    /// sending the access token to it. Redirection of the `reqwest::Client` is not required.
+    ///
+    /// Only `If-None-Match` is supported in `option`.
+    ///
+    /// # Results
+    /// Return `None` if `If-None-Match` is set and the file is not modified.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/api/driveitem-get-content?view=graph-rest-1.0&tabs=http)
+    pub async fn download_with_option<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        option: ObjectOption<DriveItemField>,
+    ) -> Result<Option<impl Stream<Item = Result<Bytes>> + Send + 'static>> {
+        let resp = self
+            .download_response("download_with_option", item.into(), option, None, None)
+            .await?;
+        Ok(resp.map(|resp| resp.bytes_stream().map_err(Error::from)))
+    }
+
+    /// Shortcut to [`download_with_option`][with_opt] with default options.
+    ///
+    /// # See also
+    /// [`download_with_option`][with_opt]
+    ///
+    /// [with_opt]: #method.download_with_option
+    pub async fn download<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+    ) -> Result<impl Stream<Item = Result<Bytes>> + Send + 'static> {
+        self.download_with_option(item, Default::default())
+            .await?
+            .ok_or_else(|| Error::unexpected_response("Unexpected empty response"))
+    }
+
+    /// Download the content of a file into `writer`, and return the number of bytes written.
+    ///
+    /// `writer` is flushed on success. On failure, some data may be already written.
+    ///
+    /// # Results
+    /// Return `None` if `If-None-Match` is set and the file is not modified. Nothing is written
+    /// in this case.
+    ///
+    /// # See also
+    /// [`download_with_option`][download]
+    ///
+    /// [download]: #method.download_with_option
+    pub async fn download_to_writer_with_option<'a, W: AsyncWrite + Unpin + ?Sized>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        option: ObjectOption<DriveItemField>,
+        writer: &mut W,
+    ) -> Result<Option<u64>> {
+        let resp = self
+            .download_response(
+                "download_to_writer_with_option",
+                item.into(),
+                option,
+                None,
+                None,
+            )
+            .await?;
+        match resp {
+            None => Ok(None),
+            Some(resp) => Ok(Some(write_response(resp, writer).await?)),
+        }
+    }
+
+    /// Shortcut to [`download_to_writer_with_option`][with_opt] with default options.
+    ///
+    /// # See also
+    /// [`download_to_writer_with_option`][with_opt]
+    ///
+    /// [with_opt]: #method.download_to_writer_with_option
+    pub async fn download_to_writer<'a, W: AsyncWrite + Unpin + ?Sized>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        writer: &mut W,
+    ) -> Result<u64> {
+        self.download_to_writer_with_option(item, Default::default(), writer)
+            .await?
+            .ok_or_else(|| Error::unexpected_response("Unexpected empty response"))
+    }
+
+    /// Download the content of a file into a local file at `path`, and return the number of
+    /// bytes written.
+    ///
+    /// The content is written to a temporary file `<path>.part` first, which is renamed to
+    /// `path` after the download completes, replacing the existing one. The temporary file is
+    /// removed on failure.
+    ///
+    /// # Results
+    /// Return `None` if `If-None-Match` is set and the file is not modified. The local file is
+    /// left untouched in this case.
+    ///
+    /// # See also
+    /// [`download_with_option`][download]
+    ///
+    /// [download]: #method.download_with_option
+    pub async fn download_to_file_with_option<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        option: ObjectOption<DriveItemField>,
+        path: impl AsRef<Path>,
+    ) -> Result<Option<u64>> {
+        let resp = self
+            .download_response(
+                "download_to_file_with_option",
+                item.into(),
+                option,
+                None,
+                None,
+            )
+            .await?;
+        match resp {
+            None => Ok(None),
+            Some(resp) => Ok(Some(write_response_to_file(resp, path.as_ref()).await?)),
+        }
+    }
+
+    /// Shortcut to [`download_to_file_with_option`][with_opt] with default options.
+    ///
+    /// # See also
+    /// [`download_to_file_with_option`][with_opt]
+    ///
+    /// [with_opt]: #method.download_to_file_with_option
+    pub async fn download_to_file<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        path: impl AsRef<Path>,
+    ) -> Result<u64> {
+        self.download_to_file_with_option(item, Default::default(), path)
+            .await?
+            .ok_or_else(|| Error::unexpected_response("Unexpected empty response"))
+    }
+
+    /// Download the content of a file converted into `format` as a stream of bytes.
+    ///
+    /// The redirection to the pre-authorized download URL of the converted file is followed
+    /// internally.
+    ///
+    /// Only `If-None-Match` is supported in `option`.
+    ///
+    /// # Results
+    /// Return `None` if `If-None-Match` is set and the file is not modified.
+    ///
+    /// # Errors
+    /// Will result in `Err` with HTTP `406 NOT ACCEPTABLE` or `notSupported` if the type of the
+    /// file cannot be converted into `format`. See [`ConvertFormat`] for supported types.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/api/driveitem-get-content-format?view=graph-rest-1.0)
+    pub async fn download_converted_with_option<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        format: ConvertFormat,
+        option: ObjectOption<DriveItemField>,
+    ) -> Result<Option<impl Stream<Item = Result<Bytes>> + Send + 'static>> {
+        let resp = self
+            .download_response(
+                "download_converted_with_option",
+                item.into(),
+                option,
+                Some(format),
+                None,
+            )
+            .await?;
+        Ok(resp.map(|resp| resp.bytes_stream().map_err(Error::from)))
+    }
+
+    /// Shortcut to [`download_converted_with_option`][with_opt] with default options.
+    ///
+    /// # See also
+    /// [`download_converted_with_option`][with_opt]
+    ///
+    /// [with_opt]: #method.download_converted_with_option
+    pub async fn download_converted<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        format: ConvertFormat,
+    ) -> Result<impl Stream<Item = Result<Bytes>> + Send + 'static> {
+        self.download_converted_with_option(item, format, Default::default())
+            .await?
+            .ok_or_else(|| Error::unexpected_response("Unexpected empty response"))
+    }
+
+    /// Download the content of a file converted into `format` into a local file at `path`, and
+    /// return the number of bytes written.
+    ///
+    /// The local file is written the same way as [`download_to_file_with_option`][to_file].
+    ///
+    /// # Errors
+    /// Will result in `Err` with HTTP `406 NOT ACCEPTABLE` or `notSupported` if the type of the
+    /// file cannot be converted into `format`. See [`ConvertFormat`] for supported types.
+    ///
+    /// # See also
+    /// [`download_converted_with_option`][download]
+    ///
+    /// [to_file]: #method.download_to_file_with_option
+    /// [download]: #method.download_converted_with_option
+    pub async fn download_converted_to_file<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        format: ConvertFormat,
+        path: impl AsRef<Path>,
+    ) -> Result<u64> {
+        let resp = self
+            .download_response(
+                "download_converted_to_file",
+                item.into(),
+                Default::default(),
+                Some(format),
+                None,
+            )
+            .await?
+            .ok_or_else(|| Error::unexpected_response("Unexpected empty response"))?;
+        write_response_to_file(resp, path.as_ref()).await
+    }
+
+    /// Download a byte range of a file.
+    ///
+    /// `range` can be a `Range<u64>` like `start..end`, a `RangeFrom<u64>` like `start..`, or an
+    /// [`ExpectRange`]. It is sent as the `Range` header to the pre-authorized download URL.
+    /// The range returned may be shorter than the requested one if it exceeds the end of the
+    /// file.
+    ///
+    /// Only `If-None-Match` is supported in `option`.
+    ///
+    /// # Results
+    /// Return `None` if `If-None-Match` is set and the file is not modified.
+    ///
+    /// # Errors
+    /// * Will result in `Err` with HTTP `416 RANGE NOT SATISFIABLE` if `range` starts at or
+    ///   beyond the end of the file.
+    /// * Will result in `Err` if the response is not `206 Partial Content`, or its
+    ///   `Content-Range` mismatches `range`.
+    ///
+    /// # Panics
+    /// Panic if `range` is empty.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/api/driveitem-get-content?view=graph-rest-1.0&tabs=http#partial-range-downloads)
+    pub async fn download_range_with_option<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        range: impl Into<ExpectRange>,
+        option: ObjectOption<DriveItemField>,
+    ) -> Result<Option<RangedDownload>> {
+        let range = range.into();
+        let resp = self
+            .download_response(
+                "download_range_with_option",
+                item.into(),
+                option,
+                None,
+                Some(range),
+            )
+            .await?;
+        resp.map(|resp| RangedDownload::from_response(resp, range))
+            .transpose()
+    }
+
+    /// Shortcut to [`download_range_with_option`][with_opt] with default options.
+    ///
+    /// # Panics
+    /// Panic if `range` is empty.
+    ///
+    /// # See also
+    /// [`download_range_with_option`][with_opt]
+    ///
+    /// [with_opt]: #method.download_range_with_option
+    pub async fn download_range<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        range: impl Into<ExpectRange>,
+    ) -> Result<RangedDownload> {
+        self.download_range_with_option(item, range, Default::default())
+            .await?
+            .ok_or_else(|| Error::unexpected_response("Unexpected empty response"))
+    }
+
+    /// Download the content of a file into a local file at `path`, resuming the previous
+    /// interrupted download if any, and return the size of the file.
+    ///
+    /// The content is appended to the temporary file `<path>.part` from its current length,
+    /// which is renamed to `path` after the download completes. The temporary file is kept on
+    /// failure, so that calling this again resumes the download.
+    ///
+    /// `e_tag` is the expected eTag of the file, usually [`DriveItem::e_tag`][e_tag] retrieved
+    /// before the first attempt. It is checked against the `ETag` of the download response,
+    /// or of the `DriveItem` if the response does not have one.
+    ///
+    /// # Errors
+    /// Will result in `Err` with HTTP `412 PRECONDITION FAILED` and `resourceModified` if the
+    /// file is modified and `e_tag` mismatches. The temporary file is removed in this case,
+    /// and the download should be restarted with the new eTag.
+    ///
+    /// Will result in `Err` with HTTP `416 RANGE NOT SATISFIABLE` if the temporary file is
+    /// longer than the file. The temporary file is removed in this case, so that calling this
+    /// again restarts the download.
+    ///
+    /// # See also
+    /// [`download_range_with_option`][download_range]
+    ///
+    /// [e_tag]: ./resource/struct.DriveItem.html#structfield.e_tag
+    /// [download_range]: #method.download_range_with_option
+    pub async fn download_to_file_resumable<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        e_tag: &Tag,
+        path: impl AsRef<Path>,
+    ) -> Result<u64> {
+        let item = item.into();
+        let path = path.as_ref();
+        let tmp_path = part_path(path);
+
+        let len = match tokio::fs::metadata(&tmp_path).await {
+            Ok(meta) => meta.len(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
+        };
+        let ret = self
+            .download_response(
+                "download_to_file_resumable",
+                item,
+                Default::default(),
+                None,
+                Some(ExpectRange {
+                    start: len,
+                    end: None,
+                }),
+            )
+            .await;
+        let download = match ret {
+            // The temporary file may be already complete.
+            Err(err) if err.status_code() == Some(StatusCode::RANGE_NOT_SATISFIABLE) => {
+                let size = self.check_e_tag(item, e_tag, &tmp_path).await?;
+                if size != Some(len) {
+                    // The temporary file is longer than the file, and cannot be resumed.
+                    let _ = tokio::fs::remove_file(&tmp_path).await;
+                    return Err(err);
+                }
+                None
+            }
+            ret => {
+                let resp = ret?.ok_or_else(|| Error::unexpected_response("Unexpected 304"))?;
+                let diagnostics = ResponseDiagnostics::from_headers(resp.headers());
+                let download = RangedDownload::from_response(
+                    resp,
+                    ExpectRange {
+                        start: len,
+                        end: None,
+                    },
+                )?;
+                match download.e_tag() {
+                    Some(tag) if !same_e_tag(tag, e_tag) => {
+                        let _ = tokio::fs::remove_file(&tmp_path).await;
+                        return Err(Error::resource_modified(
+                            "The file is modified since the download started",
+                            diagnostics,
+                        ));
+                    }
+                    Some(_) => {}
+                    None => {
+                        self.check_e_tag(item, e_tag, &tmp_path).await?;
+                    }
+                }
+                Some(download)
+            }
+        };
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&tmp_path)
+            .await?;
+        let total_size = download.as_ref().and_then(RangedDownload::total_size);
+        let written = match download {
+            Some(download) => download.write_to(&mut file).await?,
+            None => 0,
+        };
+        file.sync_all().await?;
+        drop(file);
+        if total_size.is_some_and(|size| size != len + written) {
+            return Err(Error::unexpected_response("Incomplete download"));
+        }
+        tokio::fs::rename(&tmp_path, path).await?;
+        Ok(len + written)
+    }
+
+    /// Create a downloader of a large file of `size` bytes, which fetches segments of it
+    /// concurrently over multiple connections.
+    ///
+    /// `size` is usually [`DriveItem::size`][size]. See [`SegmentedDownload`] for more details.
+    ///
+    /// [size]: ./resource/struct.DriveItem.html#structfield.size
+    #[must_use]
+    pub fn segmented_download<'a>(
+        &'a self,
+        item: impl Into<ItemLocation<'a>>,
+        size: u64,
+    ) -> SegmentedDownload<'a> {
+        SegmentedDownload::new(self, item.into(), size)
+    }
+
+    /// Check the eTag of the `DriveItem` for resumable downloads, and return its size.
+    /// Remove the temporary file if it mismatches.
+    async fn check_e_tag(
+        &self,
+        item: ItemLocation<'_>,
+        e_tag: &Tag,
+        tmp_path: &Path,
+    ) -> Result<Option<u64>> {
+        let resp = self
+            .send_item(
+                "download_to_file_resumable",
+                &item,
+                self.client
+                    .get(api_url![self.api_base; &self.drive, &item])
+                    .apply(
+                        ObjectOption::new().select(&[DriveItemField::e_tag, DriveItemField::size]),
+                    ),
+            )
+            .await?;
+        let resp = handle_error_response(resp).await?;
+        let diagnostics = ResponseDiagnostics::from_headers(resp.headers());
+        let drive_item: DriveItem = resp.json().await?;
+        if drive_item.e_tag.is_some_and(|tag| same_e_tag(&tag, e_tag)) {
+            return Ok(drive_item.size.and_then(|size| u64::try_from(size).ok()));
+        }
+        let _ = tokio::fs::remove_file(tmp_path).await;
+        Err(Error::resource_modified(
+            "The file is modified since the download started",
+            diagnostics,
+        ))
+    }
+
+    /// Request the content of a file, following the redirection to the download URL.
+    /// Return `None` on `304 Not Modified`.
+    ///
+    /// `Range` is set on both requests, in case the redirection is followed by the
+    /// `reqwest::Client`.
+    async fn download_response(
+        &self,
+        op: &'static str,
+        item: ItemLocation<'_>,
+        option: ObjectOption<DriveItemField>,
+        format: Option<ConvertFormat>,
+        range: Option<ExpectRange>,
+    ) -> Result<Option<Response>> {
+        let range = range.map(range_header);
+        let with_range = |req: RequestBuilder| match &range {
+            Some(range) => req.header(header::RANGE, range),
+            None => req,
+        };
+        let resp = self
+            .send_with_option(
+                op,
+                Some(&item),
+                with_range(
+                    self.client
+                        .get(api_url![self.api_base; &self.drive, &item, "content"])
+                        .query(&[("format", format)]),
+                ),
+                option,
+            )
+            .await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let resp = handle_error_response(resp).await?;
+        if !resp.status().is_redirection() {
+            // Redirection is followed by the `reqwest::Client`.
+            return Ok(Some(resp));
+        }
+
+        let url = resp
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| resp.url().join(v).ok())
+            .ok_or_else(|| Error::unexpected_response("Invalid header `Location` of download"))?;
+        let resp = send_pre_authenticated(op, with_range(self.client.get(url))).await?;
+        let resp = handle_error_response(resp).await?;
+        if !resp.status().is_success() {
+            return Err(Error::unexpected_response(
+                "Unexpected redirection of download URL",
+            ));
+        }
+        Ok(Some(resp))
+    }
+
     /// Create a new [`DriveItem`][drive_item] allowing to set supported attributes.
     /// [`DriveItem`][drive_item] resources have facets modeled as properties that provide data
     /// about the [`DriveItem`][drive_item]'s identities and capabilities. You must provide one
@@ -318,18 +1143,21 @@ impl OneDrive {
             .get_conflict_behavior()
             .unwrap_or(ConflictBehavior::Fail);
 
-        self.client
-            .post(api_url![&self.drive, &parent_item.into(), "children"])
-            .bearer_auth(&self.token)
-            .apply(option)
-            .json(&Req {
-                conflict_behavior,
-                drive_item,
-            })
-            .send()
-            .await?
-            .parse()
-            .await
+        let parent_item = parent_item.into();
+        self.send_with_option(
+            "create_drive_item",
+            Some(&parent_item),
+            self.client
+                .post(api_url![self.api_base; &self.drive, &parent_item, "children"])
+                .json(&Req {
+                    conflict_behavior,
+                    drive_item,
+                }),
+            option,
+        )
+        .await?
+        .parse()
+        .await
     }
 
     /// Create a new folder under an `DriveItem`
@@ -397,15 +1225,18 @@ impl OneDrive {
         patch: &DriveItem,
         option: ObjectOption<DriveItemField>,
     ) -> Result<DriveItem> {
-        self.client
-            .patch(api_url![&self.drive, &item.into()])
-            .bearer_auth(&self.token)
-            .apply(option)
-            .json(patch)
-            .send()
-            .await?
-            .parse()
-            .await
+        let item = item.into();
+        self.send_with_option(
+            "update_item_with_option",
+            Some(&item),
+            self.client
+                .patch(api_url![self.api_base; &self.drive, &item])
+                .json(patch),
+            option,
+        )
+        .await?
+        .parse()
+        .await
     }
 
     /// Shortcut to `update_item_with_option` with default options.
@@ -442,26 +1273,64 @@ impl OneDrive {
     /// only supports files up to [`Self::UPLOAD_SMALL_MAX_SIZE`]. The length is not checked
     /// locally and request will still be sent for large data.
     ///
+    /// # Errors
+    /// Will result in `Err` with HTTP `409 CONFLICT` if [`conflict_behavior`][conflict_behavior]
+    /// is not set or set to [`Fail`][conflict_fail], and the target already exists.
+    ///
+    /// # Note
+    /// [`if_match`][if_match] and [`conflict_behavior`][conflict_behavior] are supported.
+    ///
     /// # See also
     /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/api/driveitem-put-content?view=graph-rest-1.0)
     ///
-    /// [drive_item]: ./resource/struct.DriveItem.html
-    pub async fn upload_small<'a>(
+    /// [conflict_behavior]: ./option/struct.DriveItemPutOption.html#method.conflict_behavior
+    /// [conflict_fail]: ./enum.ConflictBehavior.html#variant.Fail
+    /// [if_match]: ./option/struct.DriveItemPutOption.html#method.if_match
+    pub async fn upload_small_with_option<'a>(
         &self,
         item: impl Into<ItemLocation<'a>>,
         data: impl Into<Bytes>,
+        option: DriveItemPutOption,
     ) -> Result<DriveItem> {
         let data = data.into();
-        self.client
-            .put(api_url![&self.drive, &item.into(), "content"])
-            .bearer_auth(&self.token)
-            .header(header::CONTENT_TYPE, "application/octet-stream")
-            .header(header::CONTENT_LENGTH, data.len().to_string())
-            .body(data)
-            .send()
-            .await?
-            .parse()
-            .await
+        let conflict_behavior = option
+            .get_conflict_behavior()
+            .unwrap_or(ConflictBehavior::Fail);
+        let item = item.into();
+        self.send_with_option(
+            "upload_small_with_option",
+            Some(&item),
+            self.client
+                .put(api_url![self.api_base; &self.drive, &item, "content"])
+                .query(&[("@microsoft.graph.conflictBehavior", conflict_behavior)])
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .header(header::CONTENT_LENGTH, data.len().to_string())
+                .body(data),
+            option,
+        )
+        .await?
+        .parse()
+        .await
+    }
+
+    /// Shortcut to [`upload_small_with_option`][with_opt] with `ConflictBehavior::Replace`,
+    /// which creates a new file or replaces the contents of an existing one.
+    ///
+    /// # See also
+    /// [`upload_small_with_option`][with_opt]
+    ///
+    /// [with_opt]: #method.upload_small_with_option
+    pub async fn upload_small<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        data: impl Into<Bytes>,
+    ) -> Result<DriveItem> {
+        self.upload_small_with_option(
+            item,
+            data,
+            DriveItemPutOption::new().conflict_behavior(ConflictBehavior::Replace),
+        )
+        .await
     }
 
     /// Create an upload session.
@@ -516,18 +1385,21 @@ impl OneDrive {
         let conflict_behavior = option
             .get_conflict_behavior()
             .unwrap_or(ConflictBehavior::Fail);
+        let item = item.into();
         let resp: Resp = self
-            .client
-            .post(api_url![&self.drive, &item.into(), "createUploadSession"])
-            .apply(option)
-            .bearer_auth(&self.token)
-            .json(&Req {
-                item: Item {
-                    conflict_behavior,
-                    initial,
-                },
-            })
-            .send()
+            .send_with_option(
+                "new_upload_session_with_initial_option",
+                Some(&item),
+                self.client
+                    .post(api_url![self.api_base; &self.drive, &item, "createUploadSession"])
+                    .json(&Req {
+                        item: Item {
+                            conflict_behavior,
+                            initial,
+                        },
+                    }),
+                option,
+            )
             .await?
             .parse()
             .await?;
@@ -564,6 +1436,217 @@ impl OneDrive {
             .await
     }
 
+    /// The size of parts uploaded by [`upload_with_option`][upload], which is a multiple of
+    /// [`UploadSession::PART_SIZE_ALIGNMENT`].
+    ///
+    /// [upload]: #method.upload_with_option
+    pub const UPLOAD_PART_SIZE: usize = 10 << 20; // 10 MiB
+
+    /// Upload `size` bytes read from `reader` as the content of a file, and return the
+    /// resulting `DriveItem`.
+    ///
+    /// Data up to [`OneDrive::UPLOAD_SMALL_MAX_SIZE`] bytes is uploaded in a single request.
+    /// Larger data is uploaded through an [`UploadSession`] in parts of
+    /// [`OneDrive::UPLOAD_PART_SIZE`] bytes, and only one part is buffered in memory at a time.
+    /// Parts are sent under the [`RateLimiter`], if any, but never replayed blindly. On retryable
+    /// failures or range conflicts, the upload is resumed from the next expected range of the
+    /// session, until the attempts of the [`RetryPolicy`] are exhausted. The upload session is
+    /// cancelled on other failures.
+    ///
+    /// Exactly `size` bytes are read from `reader`. It fails if `reader` ends earlier.
+    ///
+    /// # Errors
+    /// Will result in `Err` with HTTP `409 CONFLICT` if [`conflict_behavior`][conflict_behavior]
+    /// is not set or set to [`Fail`][conflict_fail], and the target already exists.
+    ///
+    /// # Note
+    /// [`if_match`][if_match], [`conflict_behavior`][conflict_behavior] and
+    /// [`retry_policy`][retry_policy] are supported.
+    ///
+    /// # See also
+    /// [`OneDrive::upload_small_with_option`][upload_small],
+    /// [`OneDrive::new_upload_session_with_option`][new_session]
+    ///
+    /// [conflict_behavior]: ./option/struct.DriveItemPutOption.html#method.conflict_behavior
+    /// [conflict_fail]: ./enum.ConflictBehavior.html#variant.Fail
+    /// [if_match]: ./option/struct.DriveItemPutOption.html#method.if_match
+    /// [retry_policy]: ./option/struct.DriveItemPutOption.html#method.retry_policy
+    /// [upload_small]: #method.upload_small_with_option
+    /// [new_session]: #method.new_upload_session_with_option
+    pub async fn upload_with_option<'a, R: AsyncRead + Unpin + ?Sized>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        reader: &mut R,
+        size: u64,
+        option: DriveItemPutOption,
+    ) -> Result<DriveItem> {
+        let item = item.into();
+        let small_size = usize::try_from(size)
+            .ok()
+            .filter(|&size| size <= Self::UPLOAD_SMALL_MAX_SIZE);
+        if let Some(size) = small_size {
+            let mut data = vec![0u8; size];
+            reader.read_exact(&mut data).await?;
+            return self.upload_small_with_option(item, data, option).await;
+        }
+
+        let retry_policy = option.get_retry_policy().cloned();
+        let retry_policy = retry_policy.as_ref().or(self.retry_policy.as_ref());
+        let (session, _) = self.new_upload_session_with_option(item, option).await?;
+        let ret = self
+            .upload_parts(&session, reader, size, retry_policy)
+            .await;
+        // Keep the session on retryable errors. It is cleaned up when expired.
+        if matches!(&ret, Err(err) if !RetryPolicy::is_retryable_error(err)) {
+            let _ = session.delete(&self.client).await;
+        }
+        ret
+    }
+
+    /// Shortcut to [`upload_with_option`][with_opt] with `ConflictBehavior::Fail`.
+    ///
+    /// # See also
+    /// [`upload_with_option`][with_opt]
+    ///
+    /// [with_opt]: #method.upload_with_option
+    pub async fn upload<'a, R: AsyncRead + Unpin + ?Sized>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        reader: &mut R,
+        size: u64,
+    ) -> Result<DriveItem> {
+        self.upload_with_option(item, reader, size, Default::default())
+            .await
+    }
+
+    /// Upload a local file at `path` as the content of a file, and return the resulting
+    /// `DriveItem`.
+    ///
+    /// # See also
+    /// [`upload_with_option`][upload]
+    ///
+    /// [upload]: #method.upload_with_option
+    pub async fn upload_file_with_option<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        path: impl AsRef<Path>,
+        option: DriveItemPutOption,
+    ) -> Result<DriveItem> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let size = file.metadata().await?.len();
+        self.upload_with_option(item, &mut file, size, option).await
+    }
+
+    /// Shortcut to [`upload_file_with_option`][with_opt] with `ConflictBehavior::Fail`.
+    ///
+    /// # See also
+    /// [`upload_file_with_option`][with_opt]
+    ///
+    /// [with_opt]: #method.upload_file_with_option
+    pub async fn upload_file<'a>(
+        &self,
+        item: impl Into<ItemLocation<'a>>,
+        path: impl AsRef<Path>,
+    ) -> Result<DriveItem> {
+        self.upload_file_with_option(item, path, Default::default())
+            .await
+    }
+
+    /// Upload `size` bytes from `reader` to `session` part by part, resuming under
+    /// `retry_policy`.
+    async fn upload_parts<R: AsyncRead + Unpin + ?Sized>(
+        &self,
+        session: &UploadSession,
+        reader: &mut R,
+        size: u64,
+        retry_policy: Option<&RetryPolicy>,
+    ) -> Result<DriveItem> {
+        let mut offset = 0;
+        while offset < size {
+            let len = (size - offset).min(Self::UPLOAD_PART_SIZE as u64);
+            let mut data = vec![0u8; usize::try_from(len).unwrap()];
+            reader.read_exact(&mut data).await?;
+            let ret = self
+                .upload_part_resumable(session, data.into(), offset, size, retry_policy)
+                .await?;
+            offset += len;
+            if let Some(item) = ret {
+                if offset == size {
+                    return Ok(item);
+                }
+                break;
+            }
+        }
+        Err(Error::unexpected_response(
+            "Upload session completes at unexpected offset",
+        ))
+    }
+
+    /// Upload `data` at `offset` to `session`.
+    ///
+    /// Parts are not replayed blindly by the retry policy, since the server may already receive
+    /// it. Instead, on retryable failures or range conflicts (HTTP 416), the session is queried
+    /// and the upload is resumed from the next expected offset, until the attempts of the retry
+    /// policy are exhausted.
+    async fn upload_part_resumable(
+        &self,
+        session: &UploadSession,
+        mut data: Bytes,
+        mut offset: u64,
+        size: u64,
+        retry_policy: Option<&RetryPolicy>,
+    ) -> Result<Option<DriveItem>> {
+        let end = offset + data.len() as u64;
+        let ctx = OpContext {
+            retry: false,
+            ..OpContext::pre_authenticated()
+        };
+        let mut attempt = 1;
+        loop {
+            let req = session.upload_part_request(data.clone(), offset..end, size, &self.client);
+            let err = match self.send_with_policy("upload_with_option", ctx, req).await {
+                Ok(resp) => match resp.parse_optional().await {
+                    Ok(ret) => return Ok(ret),
+                    Err(err) => err,
+                },
+                Err(err) => err,
+            };
+            let Some(policy) = retry_policy.filter(|policy| attempt < policy.get_max_attempts())
+            else {
+                return Err(err);
+            };
+            if err.status_code() != Some(StatusCode::RANGE_NOT_SATISFIABLE) {
+                if !RetryPolicy::is_retryable_error(&err) {
+                    return Err(err);
+                }
+                let delay = policy.retry_delay(attempt, &err);
+                #[cfg(feature = "tracing")]
+                crate::trace::record_retry(attempt, delay);
+                tokio::time::sleep(delay).await;
+            }
+            attempt += 1;
+
+            let meta: UploadSessionMeta = self
+                .send_with_policy(
+                    "upload_with_option",
+                    OpContext::pre_authenticated(),
+                    self.client.get(session.upload_url()),
+                )
+                .await?
+                .parse()
+                .await?;
+            match meta.next_expected_ranges.first().map(|range| range.start) {
+                Some(next) if offset <= next && next < end => {
+                    data = data.slice(usize::try_from(next - offset).unwrap()..);
+                    offset = next;
+                }
+                // The part is received, but the response is lost.
+                Some(next) if next == end && end < size => return Ok(None),
+                _ => return Err(err),
+            }
+        }
+    }
+
     /// Copy a `DriveItem`.
     ///
     /// Asynchronously creates a copy of an driveItem (including any children),
@@ -593,17 +1676,20 @@ impl OneDrive {
             name: &'a str,
         }
 
+        let source_item = source_item.into();
         let raw_resp = self
-            .client
-            .post(api_url![&self.drive, &source_item.into(), "copy"])
-            .bearer_auth(&self.token)
-            .json(&Req {
-                parent_reference: ItemReference {
-                    path: api_path!(&dest_folder.into()),
-                },
-                name: dest_name.as_str(),
-            })
-            .send()
+            .send_item(
+                "copy",
+                &source_item,
+                self.client
+                    .post(api_url![self.api_base; &self.drive, &source_item, "copy"])
+                    .json(&Req {
+                        parent_reference: ItemReference {
+                            path: api_path!(&dest_folder.into()),
+                        },
+                        name: dest_name.as_str(),
+                    }),
+            )
             .await?;
 
         let url = handle_error_response(raw_resp)
@@ -659,21 +1745,24 @@ impl OneDrive {
         let conflict_behavior = option
             .get_conflict_behavior()
             .unwrap_or(ConflictBehavior::Fail);
-        self.client
-            .patch(api_url![&self.drive, &source_item.into()])
-            .bearer_auth(&self.token)
-            .apply(option)
-            .json(&Req {
-                parent_reference: ItemReference {
-                    path: api_path!(&dest_folder.into()),
-                },
-                name: dest_name.map(FileName::as_str),
-                conflict_behavior,
-            })
-            .send()
-            .await?
-            .parse()
-            .await
+        let source_item = source_item.into();
+        self.send_with_option(
+            "move_with_option",
+            Some(&source_item),
+            self.client
+                .patch(api_url![self.api_base; &self.drive, &source_item])
+                .json(&Req {
+                    parent_reference: ItemReference {
+                        path: api_path!(&dest_folder.into()),
+                    },
+                    name: dest_name.map(FileName::as_str),
+                    conflict_behavior,
+                }),
+            option,
+        )
+        .await?
+        .parse()
+        .await
     }
 
     /// Shortcut to `move_with_option` with `ConflictBehavior::Fail`.
@@ -721,14 +1810,17 @@ impl OneDrive {
             "`conflict_behavior` is not supported by `delete[_with_option]`",
         );
 
-        self.client
-            .delete(api_url![&self.drive, &item.into()])
-            .bearer_auth(&self.token)
-            .apply(option)
-            .send()
-            .await?
-            .parse_no_content()
-            .await
+        let item = item.into();
+        self.send_with_option(
+            "delete_with_option",
+            Some(&item),
+            self.client
+                .delete(api_url![self.api_base; &self.drive, &item]),
+            option,
+        )
+        .await?
+        .parse_no_content()
+        .await
     }
 
     /// Shortcut to `delete_with_option`.
@@ -773,11 +1865,13 @@ impl OneDrive {
             "`get_count` is not supported by Track Changes API",
         );
         let resp = self
-            .client
-            .get(api_url![&self.drive, "root", "delta"])
-            .apply(option)
-            .bearer_auth(&self.token)
-            .send()
+            .send_with_option(
+                "track_root_changes_from_initial_with_option",
+                None,
+                self.client
+                    .get(api_url![self.api_base; &self.drive, "root", "delta"]),
+                option,
+            )
             .await?
             .parse()
             .await?;
@@ -806,10 +1900,10 @@ impl OneDrive {
         delta_url: &str,
     ) -> Result<TrackChangeFetcher> {
         let resp: DriveItemCollectionResponse = self
-            .client
-            .get(delta_url)
-            .bearer_auth(&self.token)
-            .send()
+            .send(
+                "track_root_changes_from_delta_url",
+                self.client.get(delta_url),
+            )
             .await?
             .parse()
             .await?;
@@ -842,21 +1936,21 @@ impl OneDrive {
             !option.has_get_count(),
             "`get_count` is not supported by Track Changes API",
         );
-        self.client
-            .get(api_url![&self.drive, "root", "delta"])
-            .query(&[("token", "latest")])
-            .apply(option)
-            .bearer_auth(&self.token)
-            .send()
-            .await?
-            .parse::<DriveItemCollectionResponse>()
-            .await?
-            .delta_url
-            .ok_or_else(|| {
-                Error::unexpected_response(
-                    "Missing field `@odata.deltaLink` for getting latest delta",
-                )
-            })
+        self.send_with_option(
+            "get_root_latest_delta_url_with_option",
+            None,
+            self.client
+                .get(api_url![self.api_base; &self.drive, "root", "delta"])
+                .query(&[("token", "latest")]),
+            option,
+        )
+        .await?
+        .parse::<DriveItemCollectionResponse>()
+        .await?
+        .delta_url
+        .ok_or_else(|| {
+            Error::unexpected_response("Missing field `@odata.deltaLink` for getting latest delta")
+        })
     }
 
     /// Shortcut to `get_root_latest_delta_url_with_option` with default parameters.
@@ -871,6 +1965,14 @@ impl OneDrive {
     }
 }
 
+fn default_client() -> Client {
+    Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .gzip(true)
+        .build()
+        .unwrap()
+}
+
 /// The monitor for checking the progress of a asynchronous `copy` operation.
 ///
 /// # Notes
@@ -952,14 +2054,13 @@ impl CopyProgressMonitor {
     /// [copy_progress]: ./struct.CopyProgress.html
     #[cfg(feature = "beta")]
     pub async fn fetch_progress(&self, onedrive: &OneDrive) -> Result<CopyProgress> {
-        // No bearer auth.
-        onedrive
-            .client
-            .get(&self.monitor_url)
-            .send()
-            .await?
-            .parse()
-            .await
+        send_pre_authenticated(
+            "CopyProgressMonitor::fetch_progress",
+            onedrive.client.get(&self.monitor_url),
+        )
+        .await?
+        .parse()
+        .await
     }
 }
 
@@ -975,21 +2076,28 @@ struct DriveItemCollectionResponse {
 #[derive(Debug)]
 struct DriveItemFetcher {
     last_response: DriveItemCollectionResponse,
+    /// The index of the next page to request, recorded with feature `tracing`.
+    next_page: u32,
 }
 
 impl DriveItemFetcher {
     fn new(first_response: DriveItemCollectionResponse) -> Self {
         Self {
             last_response: first_response,
+            next_page: 1,
         }
     }
 
     fn resume_from(next_url: impl Into<String>) -> Self {
-        Self::new(DriveItemCollectionResponse {
-            value: None,
-            next_url: Some(next_url.into()),
-            delta_url: None,
-        })
+        Self {
+            last_response: DriveItemCollectionResponse {
+                value: None,
+                next_url: Some(next_url.into()),
+                delta_url: None,
+            },
+            // Pages are counted from the resumed one.
+            next_page: 0,
+        }
     }
 
     fn next_url(&self) -> Option<&str> {
@@ -1009,27 +2117,37 @@ impl DriveItemFetcher {
         self.last_response.delta_url.as_deref()
     }
 
-    async fn fetch_next_page(&mut self, onedrive: &OneDrive) -> Result<Option<Vec<DriveItem>>> {
+    async fn fetch_next_page(
+        &mut self,
+        onedrive: &OneDrive,
+        op: &'static str,
+    ) -> Result<Option<Vec<DriveItem>>> {
         if let Some(items) = self.last_response.value.take() {
             return Ok(Some(items));
         }
         let Some(url) = self.last_response.next_url.as_ref() else {
             return Ok(None);
         };
+        let ctx = OpContext {
+            page: Some(self.next_page),
+            ..OpContext::default()
+        };
         self.last_response = onedrive
-            .client
-            .get(url)
-            .bearer_auth(&onedrive.token)
-            .send()
+            .send_with_policy(op, ctx, onedrive.client.get(url))
             .await?
             .parse()
             .await?;
+        self.next_page += 1;
         Ok(Some(self.last_response.value.take().unwrap_or_default()))
     }
 
-    async fn fetch_all(mut self, onedrive: &OneDrive) -> Result<(Vec<DriveItem>, Option<String>)> {
+    async fn fetch_all(
+        mut self,
+        onedrive: &OneDrive,
+        op: &'static str,
+    ) -> Result<(Vec<DriveItem>, Option<String>)> {
         let mut buf = vec![];
-        while let Some(items) = self.fetch_next_page(onedrive).await? {
+        while let Some(items) = self.fetch_next_page(onedrive, op).await? {
             buf.extend(items);
         }
         Ok((buf, self.delta_url().map(Into::into)))
@@ -1084,7 +2202,9 @@ impl ListChildrenFetcher {
 
     /// Fetch the next page, or `None` if reaches the end.
     pub async fn fetch_next_page(&mut self, onedrive: &OneDrive) -> Result<Option<Vec<DriveItem>>> {
-        self.fetcher.fetch_next_page(onedrive).await
+        self.fetcher
+            .fetch_next_page(onedrive, "ListChildrenFetcher::fetch_next_page")
+            .await
     }
 
     /// Fetch all rest pages and collect all items.
@@ -1095,7 +2215,7 @@ impl ListChildrenFetcher {
     /// all progress will be lost.
     pub async fn fetch_all(self, onedrive: &OneDrive) -> Result<Vec<DriveItem>> {
         self.fetcher
-            .fetch_all(onedrive)
+            .fetch_all(onedrive, "ListChildrenFetcher::fetch_all")
             .await
             .map(|(items, _)| items)
     }
@@ -1173,7 +2293,9 @@ impl TrackChangeFetcher {
 
     /// Fetch the next page, or `None` if reaches the end.
     pub async fn fetch_next_page(&mut self, onedrive: &OneDrive) -> Result<Option<Vec<DriveItem>>> {
-        self.fetcher.fetch_next_page(onedrive).await
+        self.fetcher
+            .fetch_next_page(onedrive, "TrackChangeFetcher::fetch_next_page")
+            .await
     }
 
     /// Fetch all rest pages, collect all items, and also return `delta_url`.
@@ -1183,7 +2305,10 @@ impl TrackChangeFetcher {
     /// Any error occurs when fetching will lead to an failure, and
     /// all progress will be lost.
     pub async fn fetch_all(self, onedrive: &OneDrive) -> Result<(Vec<DriveItem>, String)> {
-        let (items, opt_delta_url) = self.fetcher.fetch_all(onedrive).await?;
+        let (items, opt_delta_url) = self
+            .fetcher
+            .fetch_all(onedrive, "TrackChangeFetcher::fetch_all")
+            .await?;
         let delta_url = opt_delta_url.ok_or_else(|| {
             Error::unexpected_response("Missing `@odata.deltaLink` for the last page")
         })?;
@@ -1237,6 +2362,14 @@ impl UploadSession {
     /// [`upload_part`]: #method.upload_part
     pub const MAX_PART_SIZE: usize = 60 << 20; // 60 MiB
 
+    /// The size of each part except the last one must be a multiple of this, when a file is
+    /// split into multiple parts.
+    ///
+    /// See [`upload_part`] for more details.
+    ///
+    /// [`upload_part`]: #method.upload_part
+    pub const PART_SIZE_ALIGNMENT: usize = 320 << 10; // 320 KiB
+
     /// Construct back the upload session from upload URL.
     pub fn from_upload_url(upload_url: impl Into<String>) -> Self {
         Self {
@@ -1250,10 +2383,7 @@ impl UploadSession {
     /// # See also
     /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/api/driveitem-createuploadsession?view=graph-rest-1.0#resuming-an-in-progress-upload)
     pub async fn get_meta(&self, client: &Client) -> Result<UploadSessionMeta> {
-        // No bearer auth.
-        client
-            .get(&self.upload_url)
-            .send()
+        send_pre_authenticated("UploadSession::get_meta", client.get(&self.upload_url))
             .await?
             .parse::<UploadSessionMeta>()
             .await
@@ -1282,10 +2412,7 @@ impl UploadSession {
     /// # See also
     /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/api/driveitem-createuploadsession?view=graph-rest-1.0#cancel-the-upload-session)
     pub async fn delete(&self, client: &Client) -> Result<()> {
-        // No bearer auth.
-        client
-            .delete(&self.upload_url)
-            .send()
+        send_pre_authenticated("UploadSession::delete", client.delete(&self.upload_url))
             .await?
             .parse_no_content()
             .await
@@ -1330,9 +2457,23 @@ impl UploadSession {
         file_size: u64,
         client: &Client,
     ) -> Result<Option<DriveItem>> {
+        let req = self.upload_part_request(data.into(), remote_range, file_size, client);
+        send_pre_authenticated("UploadSession::upload_part", req)
+            .await?
+            .parse_optional()
+            .await
+    }
+
+    /// Build the request of [`upload_part`](#method.upload_part).
+    fn upload_part_request(
+        &self,
+        data: Bytes,
+        remote_range: std::ops::Range<u64>,
+        file_size: u64,
+        client: &Client,
+    ) -> RequestBuilder {
         use std::convert::TryFrom as _;
 
-        let data = data.into();
         assert!(!data.is_empty(), "Empty data");
         assert!(
             remote_range.start < remote_range.end && remote_range.end <= file_size
@@ -1341,29 +2482,93 @@ impl UploadSession {
             "Invalid remote range",
         );
 
-        // No bearer auth.
-        client
-            .put(&self.upload_url)
-            .header(
-                header::CONTENT_RANGE,
-                format!(
-                    "bytes {}-{}/{}",
-                    remote_range.start,
-                    // Inclusive.
-                    // We checked `remote_range.start < remote_range.end`,
-                    // so this never overflows.
-                    remote_range.end - 1,
-                    file_size,
-                ),
-            )
-            .body(data)
-            .send()
-            .await?
-            .parse_optional()
-            .await
+        let req = client.put(&self.upload_url).header(
+            header::CONTENT_RANGE,
+            format!(
+                "bytes {}-{}/{}",
+                remote_range.start,
+                // Inclusive.
+                // We checked `remote_range.start < remote_range.end`,
+                // so this never overflows.
+                remote_range.end - 1,
+                file_size,
+            ),
+        );
+        req.body(data)
+    }
+}
+
+/// Set `client-request-id` of `req` by `generator`, if it is not set yet.
+fn with_default_client_request_id(
+    req: RequestBuilder,
+    generator: &(dyn Fn() -> String + Send + Sync),
+) -> Result<RequestBuilder> {
+    const CLIENT_REQUEST_ID: &str = "client-request-id";
+
+    let (client, req) = req.build_split();
+    let mut req = req?;
+    if !req.headers().contains_key(CLIENT_REQUEST_ID) {
+        let id = header::HeaderValue::from_str(&generator())
+            .map_err(|_| Error::unexpected_response("Invalid generated client-request-id"))?;
+        req.headers_mut().insert(CLIENT_REQUEST_ID, id);
+    }
+    Ok(RequestBuilder::from_parts(client, req))
+}
+
+/// The context of an operation, which determines how its requests are sent and recorded.
+#[derive(Clone, Copy)]
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+struct OpContext<'a, 'b> {
+    /// Whether requests are authorized, or sent as is to pre-authenticated URLs, eg. of upload
+    /// sessions.
+    authorized: bool,
+    /// The item operated on, recorded with feature `tracing`.
+    item: Option<&'a ItemLocation<'b>>,
+    /// The page index of fetchers starting from 0, recorded with feature `tracing`.
+    page: Option<u32>,
+    /// The retry policy overriding the one of `OneDrive`, eg. from options of the call.
+    retry_policy: Option<&'a RetryPolicy>,
+    /// Whether requests are retried under the retry policy. It is disabled if the caller
+    /// handles failures itself, eg. resuming upload sessions.
+    retry: bool,
+}
+
+impl Default for OpContext<'_, '_> {
+    fn default() -> Self {
+        Self {
+            authorized: true,
+            item: None,
+            page: None,
+            retry_policy: None,
+            retry: true,
+        }
+    }
+}
+
+impl OpContext<'_, '_> {
+    fn pre_authenticated() -> Self {
+        Self {
+            authorized: false,
+            ..Self::default()
+        }
     }
 }
 
+/// Send a request to a pre-authenticated URL without bearer auth, and record the result with
+/// feature `tracing`.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+async fn send_pre_authenticated(op: &'static str, req: RequestBuilder) -> Result<Response> {
+    let fut = async {
+        let ret = req.send().await;
+        #[cfg(feature = "tracing")]
+        trace::record_response(&ret, None);
+        Ok(ret?)
+    };
+    #[cfg(feature = "tracing")]
+    let fut = tracing::Instrument::instrument(fut, trace::span(op, None, None, None));
+    fut.await
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1381,6 +2586,17 @@ mod test {
             api_path!(&ItemLocation::from_path("/dir/file name").unwrap()),
             "/drive/root:%2Fdir%2Ffile%20name:",
         );
+
+        let drive = OneDrive::new("some-token", DriveLocation::me());
+        assert_eq!(
+            api_url![drive.api_base; &drive.drive, "root"].as_str(),
+            "https://graph.microsoft.com/v1.0/me/drive/root",
+        );
+        let drive = drive.with_cloud(&Cloud::us_gov());
+        assert_eq!(
+            api_url![drive.api_base; &drive.drive, "root"].as_str(),
+            "https://graph.microsoft.us/v1.0/me/drive/root",
+        );
     }
 
     #[test]
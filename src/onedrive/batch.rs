@@ -0,0 +1,376 @@
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+use super::{ItemReference, OneDrive};
+use crate::{
+    error::{Error, Result},
+    resource::DriveItem,
+    util::{handle_error_response, ApiPathComponent, FileName, ItemLocation, ResponseExt as _},
+    ConflictBehavior,
+};
+use bytes::Bytes;
+use reqwest::{
+    header::{self, HeaderName, HeaderValue},
+    Method, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use url::Url;
+
+/// A builder of JSON batch requests, to combine multiple operations into one request.
+///
+/// Operations are queued by methods like [`BatchRequest::get_item`], which return
+/// [`BatchHandle`]s to take their results from the [`BatchResponse`] after
+/// [`send`][send]. At most [`BatchRequest::MAX_REQUESTS`] operations are sent in one
+/// request, and larger batches are split automatically.
+///
+/// Operations are executed in any order unless ordered by [`BatchRequest::depends_on`].
+///
+/// # Example
+/// ```
+/// use onedrive_api::{FileName, ItemLocation, OneDrive};
+///
+/// # async fn run(drive: &OneDrive) -> onedrive_api::Result<()> {
+/// let mut batch = drive.batch();
+/// let dir = batch.get_item(ItemLocation::from_path("/dir").unwrap());
+/// let moved = batch.move_(
+///     ItemLocation::from_path("/file").unwrap(),
+///     ItemLocation::from_path("/dir").unwrap(),
+///     None,
+/// );
+/// batch.depends_on(&moved, &dir);
+///
+/// let mut resp = batch.send().await;
+/// let dir = resp.take(&dir)?;
+/// let moved = resp.take(&moved)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/json-batching)
+///
+/// [send]: #method.send
+#[derive(Debug)]
+pub struct BatchRequest<'a> {
+    onedrive: &'a OneDrive,
+    requests: Vec<SubRequest>,
+    // Union-find of dependency groups, which must be sent in the same request.
+    group_parent: Vec<usize>,
+    group_size: Vec<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubRequest {
+    id: String,
+    method: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<String>,
+}
+
+/// A handle to an operation queued in a [`BatchRequest`], to take its result of type `T` from
+/// the [`BatchResponse`].
+pub struct BatchHandle<T> {
+    idx: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for BatchHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BatchHandle").field(&self.idx).finish()
+    }
+}
+
+impl<'a> BatchRequest<'a> {
+    /// The maximum number of operations in one JSON batch request.
+    pub const MAX_REQUESTS: usize = 20;
+
+    pub(super) fn new(onedrive: &'a OneDrive) -> Self {
+        Self {
+            onedrive,
+            requests: Vec::new(),
+            group_parent: Vec::new(),
+            group_size: Vec::new(),
+        }
+    }
+
+    /// Get the number of queued operations.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Check if there is no queued operation.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    fn push<T>(
+        &mut self,
+        method: &Method,
+        url: &Url,
+        body: Option<serde_json::Value>,
+    ) -> BatchHandle<T> {
+        let idx = self.requests.len();
+        // Relative to the API base URL, eg. `/me/drive/root`.
+        let base_len = self.onedrive.api_base.as_str().trim_end_matches('/').len();
+        self.requests.push(SubRequest {
+            id: idx.to_string(),
+            method: method.to_string(),
+            url: url.as_str()[base_len..].to_owned(),
+            headers: body
+                .as_ref()
+                .map(|_| json!({ header::CONTENT_TYPE.as_str(): "application/json" })),
+            body,
+            depends_on: Vec::new(),
+        });
+        self.group_parent.push(idx);
+        self.group_size.push(1);
+        BatchHandle {
+            idx,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Queue an operation to get a `DriveItem`.
+    ///
+    /// # See also
+    /// [`OneDrive::get_item`][get_item]
+    ///
+    /// [get_item]: ./struct.OneDrive.html#method.get_item
+    pub fn get_item<'i>(&mut self, item: impl Into<ItemLocation<'i>>) -> BatchHandle<DriveItem> {
+        let od = self.onedrive;
+        let url = api_url![od.api_base; &od.drive, &item.into()];
+        self.push(&Method::GET, &url, None)
+    }
+
+    /// Queue an operation to update a `DriveItem`.
+    ///
+    /// # Panics
+    /// Panic if `patch` cannot be serialized.
+    ///
+    /// # See also
+    /// [`OneDrive::update_item`][update_item]
+    ///
+    /// [update_item]: ./struct.OneDrive.html#method.update_item
+    pub fn update_item<'i>(
+        &mut self,
+        item: impl Into<ItemLocation<'i>>,
+        patch: &DriveItem,
+    ) -> BatchHandle<DriveItem> {
+        let od = self.onedrive;
+        let url = api_url![od.api_base; &od.drive, &item.into()];
+        let body = serde_json::to_value(patch).expect("Failed to serialize DriveItem");
+        self.push(&Method::PATCH, &url, Some(body))
+    }
+
+    /// Queue an operation to move a `DriveItem` to a new folder, with
+    /// `ConflictBehavior::Fail`.
+    ///
+    /// # See also
+    /// [`OneDrive::move_`][move_]
+    ///
+    /// [move_]: ./struct.OneDrive.html#method.move_
+    pub fn move_<'s, 'd>(
+        &mut self,
+        source_item: impl Into<ItemLocation<'s>>,
+        dest_folder: impl Into<ItemLocation<'d>>,
+        dest_name: Option<&FileName>,
+    ) -> BatchHandle<DriveItem> {
+        let od = self.onedrive;
+        let url = api_url![od.api_base; &od.drive, &source_item.into()];
+        let body = json!({
+            "parentReference": ItemReference {
+                path: api_path!(&dest_folder.into()),
+            },
+            "name": dest_name.map(FileName::as_str),
+            "@microsoft.graph.conflictBehavior": ConflictBehavior::Fail,
+        });
+        self.push(&Method::PATCH, &url, Some(body))
+    }
+
+    /// Queue an operation to delete a `DriveItem`.
+    ///
+    /// # See also
+    /// [`OneDrive::delete`][delete]
+    ///
+    /// [delete]: ./struct.OneDrive.html#method.delete
+    pub fn delete<'i>(&mut self, item: impl Into<ItemLocation<'i>>) -> BatchHandle<()> {
+        let od = self.onedrive;
+        let url = api_url![od.api_base; &od.drive, &item.into()];
+        self.push(&Method::DELETE, &url, None)
+    }
+
+    /// Make the operation of `request` executed after the one of `dependency`.
+    ///
+    /// If the dependency fails, the dependent operation fails with HTTP 424 Failed Dependency.
+    /// Operations connected by dependencies are always sent in the same request.
+    ///
+    /// # Panics
+    /// Panic if either handle is out of range of this batch, if they are the same, or if more than
+    /// [`BatchRequest::MAX_REQUESTS`] operations would be connected by dependencies.
+    pub fn depends_on<A, B>(&mut self, request: &BatchHandle<A>, dependency: &BatchHandle<B>) {
+        assert!(
+            request.idx < self.len() && dependency.idx < self.len(),
+            "Handle is out of range of this batch",
+        );
+        assert_ne!(
+            request.idx, dependency.idx,
+            "Operation cannot depend on itself"
+        );
+
+        let (a, b) = (self.group_of(request.idx), self.group_of(dependency.idx));
+        if a != b {
+            let size = self.group_size[a] + self.group_size[b];
+            assert!(
+                size <= Self::MAX_REQUESTS,
+                "Too many operations connected by dependencies",
+            );
+            self.group_parent[b] = a;
+            self.group_size[a] = size;
+        }
+        self.requests[request.idx]
+            .depends_on
+            .push(dependency.idx.to_string());
+    }
+
+    fn group_of(&mut self, mut idx: usize) -> usize {
+        while self.group_parent[idx] != idx {
+            self.group_parent[idx] = self.group_parent[self.group_parent[idx]];
+            idx = self.group_parent[idx];
+        }
+        idx
+    }
+
+    /// Split operations into chunks of at most `MAX_REQUESTS`, keeping dependency groups
+    /// together and the order of their first operations.
+    fn chunks(&mut self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut group_pos = HashMap::new();
+        for idx in 0..self.len() {
+            let group = self.group_of(idx);
+            let pos = *group_pos.entry(group).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[pos].push(idx);
+        }
+
+        let mut chunks: Vec<Vec<usize>> = Vec::new();
+        for group in groups {
+            match chunks.last_mut() {
+                Some(chunk) if chunk.len() + group.len() <= Self::MAX_REQUESTS => {
+                    chunk.extend(group);
+                }
+                _ => chunks.push(group),
+            }
+        }
+        chunks
+    }
+
+    /// Send all queued operations in one or more JSON batch requests.
+    ///
+    /// Results of operations are in the returned [`BatchResponse`]. If a batch request itself
+    /// fails, its error is the result of every operation in it, and the remaining requests are
+    /// still sent.
+    pub async fn send(mut self) -> BatchResponse {
+        #[derive(Serialize)]
+        struct Req<'a> {
+            requests: Vec<&'a SubRequest>,
+        }
+
+        #[derive(Deserialize)]
+        struct Resp {
+            responses: Vec<SubResponse>,
+        }
+
+        let od = self.onedrive;
+        let mut results = HashMap::new();
+        for chunk in self.chunks() {
+            let req = Req {
+                requests: chunk.iter().map(|&idx| &self.requests[idx]).collect(),
+            };
+            let ret = async {
+                od.send(
+                    "BatchRequest::send",
+                    od.client.post(api_url![od.api_base; "$batch"]).json(&req),
+                )
+                .await?
+                .parse::<Resp>()
+                .await
+            }
+            .await;
+            let resp = match ret {
+                Ok(resp) => resp,
+                Err(err) => {
+                    results.extend(chunk.iter().map(|&idx| (idx, Err(err.clone()))));
+                    continue;
+                }
+            };
+
+            for sub in resp.responses {
+                let Ok(idx) = sub.id.parse::<usize>() else {
+                    continue;
+                };
+                results.insert(idx, sub.into_result().await);
+            }
+        }
+        BatchResponse { results }
+    }
+}
+
+#[derive(Deserialize)]
+struct SubResponse {
+    id: String,
+    status: u16,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<serde_json::Value>,
+}
+
+impl SubResponse {
+    /// Decode the result as if it is a standalone response.
+    async fn into_result(self) -> Result<Bytes> {
+        let body = self.body.map(|v| v.to_string()).unwrap_or_default();
+        let mut resp = http::Response::new(body);
+        *resp.status_mut() = StatusCode::from_u16(self.status)
+            .map_err(|_| Error::unexpected_response("Invalid status of batch operation"))?;
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                resp.headers_mut().insert(name, value);
+            }
+        }
+        Ok(handle_error_response(resp.into()).await?.bytes().await?)
+    }
+}
+
+/// The results of operations sent by [`BatchRequest::send`].
+#[derive(Debug)]
+pub struct BatchResponse {
+    results: HashMap<usize, Result<Bytes>>,
+}
+
+impl BatchResponse {
+    /// Take the result of the operation of `handle`.
+    ///
+    /// Failed operations are decoded the same way as their non-batch counterparts.
+    /// Return `Err` if the result is missing, or has been taken.
+    pub fn take<T: DeserializeOwned>(&mut self, handle: &BatchHandle<T>) -> Result<T> {
+        let body = self
+            .results
+            .remove(&handle.idx)
+            .ok_or_else(|| Error::unexpected_response("Missing response of batch operation"))??;
+        let body = if body.is_empty() { &b"null"[..] } else { &body };
+        serde_json::from_slice(body)
+            .map_err(|_| Error::unexpected_response("Invalid response of batch operation"))
+    }
+}
@@ -0,0 +1,486 @@
+use std::{
+    collections::VecDeque,
+    io::SeekFrom,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::{send_pre_authenticated, OneDrive};
+use crate::{
+    error::{Error, ResponseDiagnostics, Result},
+    resource::{ErrorCode, Tag},
+    util::{handle_error_response, ItemLocation},
+    ExpectRange,
+};
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::TryStreamExt as _;
+use reqwest::{header, Response, StatusCode};
+use tokio::io::{AsyncSeekExt as _, AsyncWrite, AsyncWriteExt as _};
+
+/// A byte range of a file being downloaded, returned by
+/// [`OneDrive::download_range`][download_range].
+///
+/// [download_range]: ./struct.OneDrive.html#method.download_range
+#[derive(Debug)]
+pub struct RangedDownload {
+    range: Range<u64>,
+    total_size: Option<u64>,
+    e_tag: Option<Tag>,
+    resp: Response,
+}
+
+impl RangedDownload {
+    /// Validate a `206 Partial Content` response of the requested range.
+    pub(super) fn from_response(resp: Response, requested: ExpectRange) -> Result<Self> {
+        if resp.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(Error::unexpected_response(
+                "Expect 206 Partial Content for range download",
+            ));
+        }
+        let (range, total_size) = resp
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_content_range)
+            .ok_or_else(|| Error::unexpected_response("Invalid header `Content-Range`"))?;
+        if range.start != requested.start || requested.end.is_some_and(|end| end < range.end) {
+            return Err(Error::unexpected_response(
+                "Header `Content-Range` mismatches the requested range",
+            ));
+        }
+        if resp
+            .content_length()
+            .is_some_and(|len| len != range.end - range.start)
+        {
+            return Err(Error::unexpected_response(
+                "Header `Content-Length` mismatches `Content-Range`",
+            ));
+        }
+        let e_tag = resp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| Tag(v.to_owned()));
+        Ok(Self {
+            range,
+            total_size,
+            e_tag,
+            resp,
+        })
+    }
+
+    /// Get the byte range being downloaded.
+    ///
+    /// It may be shorter than the requested one if the requested one exceeds the end of the file.
+    #[must_use]
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Get the total size of the file, or `None` if it is unknown.
+    #[must_use]
+    pub fn total_size(&self) -> Option<u64> {
+        self.total_size
+    }
+
+    /// Get the `ETag` of the download response, if any.
+    ///
+    /// It identifies the version of the content, and is used to check if the file is modified
+    /// between downloads of different ranges.
+    #[must_use]
+    pub fn e_tag(&self) -> Option<&Tag> {
+        self.e_tag.as_ref()
+    }
+
+    /// Get the content of the range as a stream of bytes.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes>> + Send + 'static {
+        self.resp.bytes_stream().map_err(Error::from)
+    }
+
+    /// Write the content of the range into `writer`, and return the number of bytes written.
+    ///
+    /// `writer` is flushed on success. On failure, some data may be already written.
+    pub async fn write_to<W: AsyncWrite + Unpin + ?Sized>(self, writer: &mut W) -> Result<u64> {
+        write_response(self.resp, writer).await
+    }
+}
+
+/// A downloader of a large file, which fetches segments of it concurrently over multiple
+/// connections.
+///
+/// The file is split into segments of [`segment_size`][segment_size] bytes, which are fetched
+/// from the pre-authorized download URL by at most [`connections`][connections] requests at
+/// the same time, and written into a pre-allocated local file. The download URL is re-fetched
+/// if it expires during the transfer. Failed segments are retried one by one after other
+/// segments complete.
+///
+/// Segments are checked to have the same `ETag`, or the download fails with
+/// `resourceModified`.
+///
+/// # Example
+/// ```
+/// use onedrive_api::{ItemLocation, OneDrive};
+///
+/// # async fn run(drive: &OneDrive) -> onedrive_api::Result<()> {
+/// let loc = ItemLocation::from_path("/video.mp4").unwrap();
+/// let item = drive.get_item(loc).await?;
+/// let size = item.size.unwrap().try_into().unwrap();
+/// drive
+///     .segmented_download(loc, size)
+///     .connections(8)
+///     .download_to_file("video.mp4")
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [segment_size]: #method.segment_size
+/// [connections]: #method.connections
+#[derive(Debug)]
+pub struct SegmentedDownload<'a> {
+    onedrive: &'a OneDrive,
+    item: ItemLocation<'a>,
+    size: u64,
+    connections: usize,
+    segment_size: u64,
+    max_attempts: u32,
+}
+
+/// The state shared by segments of a [`SegmentedDownload`].
+#[derive(Debug)]
+struct SegmentState {
+    /// The download URL and its generation, which is bumped on each re-fetch.
+    url: tokio::sync::Mutex<(u64, String)>,
+    e_tag: Mutex<Option<Tag>>,
+    queue: Mutex<VecDeque<Range<u64>>>,
+    failed: Mutex<Vec<(Range<u64>, Error)>>,
+}
+
+impl<'a> SegmentedDownload<'a> {
+    /// The default maximum number of concurrent connections.
+    pub const DEFAULT_CONNECTIONS: usize = 4;
+
+    /// The default size of a segment in bytes.
+    pub const DEFAULT_SEGMENT_SIZE: u64 = 8 << 20; // 8 MiB
+
+    /// The default maximum number of attempts of a segment, including the first one.
+    pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+    pub(super) fn new(onedrive: &'a OneDrive, item: ItemLocation<'a>, size: u64) -> Self {
+        Self {
+            onedrive,
+            item,
+            size,
+            connections: Self::DEFAULT_CONNECTIONS,
+            segment_size: Self::DEFAULT_SEGMENT_SIZE,
+            max_attempts: Self::DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    /// Set the maximum number of concurrent connections.
+    ///
+    /// Default to be [`SegmentedDownload::DEFAULT_CONNECTIONS`].
+    ///
+    /// # Panics
+    /// Panic if `connections` is zero.
+    #[must_use]
+    pub fn connections(mut self, connections: usize) -> Self {
+        assert_ne!(connections, 0, "Connections must not be zero");
+        self.connections = connections;
+        self
+    }
+
+    /// Set the size of a segment in bytes. The last segment may be shorter.
+    ///
+    /// Default to be [`SegmentedDownload::DEFAULT_SEGMENT_SIZE`].
+    ///
+    /// # Panics
+    /// Panic if `segment_size` is zero.
+    #[must_use]
+    pub fn segment_size(mut self, segment_size: u64) -> Self {
+        assert_ne!(segment_size, 0, "Segment size must not be zero");
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Set the maximum number of attempts of a segment, including the first one.
+    ///
+    /// Default to be [`SegmentedDownload::DEFAULT_MAX_ATTEMPTS`]. `1` disables retrying.
+    ///
+    /// # Panics
+    /// Panic if `max_attempts` is zero.
+    #[must_use]
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        assert_ne!(max_attempts, 0, "Max attempts must not be zero");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Download the file into a local file at `path`.
+    ///
+    /// The content is written to a temporary file `<path>.part` pre-allocated to the size of
+    /// the file, which is renamed to `path` after all segments complete. The temporary file is
+    /// removed on failure.
+    ///
+    /// # Errors
+    /// Will result in `Err` with HTTP `412 PRECONDITION FAILED` and `resourceModified` if the
+    /// file is modified during the download, or its size differs from the `size` given to
+    /// [`OneDrive::segmented_download`][segmented_download], or the last error of a segment if
+    /// it still fails after `max_attempts` attempts.
+    ///
+    /// [segmented_download]: ./struct.OneDrive.html#method.segmented_download
+    pub async fn download_to_file(self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp_path = part_path(path);
+
+        let ret = async {
+            let file = tokio::fs::File::create(&tmp_path).await?;
+            file.set_len(self.size).await?;
+            self.download_segments(&tmp_path).await?;
+            file.sync_all().await?;
+            drop(file);
+            tokio::fs::rename(&tmp_path, path).await?;
+            Ok(())
+        }
+        .await;
+        if ret.is_err() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+        }
+        ret
+    }
+
+    async fn download_segments(&self, path: &Path) -> Result<()> {
+        let segments = (0..self.size.div_ceil(self.segment_size))
+            .map(|i| {
+                let start = i * self.segment_size;
+                start..(start + self.segment_size).min(self.size)
+            })
+            .collect::<VecDeque<_>>();
+        if segments.is_empty() {
+            return Ok(());
+        }
+
+        let url = self.onedrive.get_item_download_url(self.item).await?;
+        let workers = self.connections.min(segments.len());
+        let state = SegmentState {
+            url: tokio::sync::Mutex::new((0, url)),
+            e_tag: Mutex::new(None),
+            queue: Mutex::new(segments),
+            failed: Mutex::new(Vec::new()),
+        };
+        futures_util::future::try_join_all((0..workers).map(|_| self.worker(&state, path))).await?;
+
+        // Retry failed segments one by one.
+        let failed = std::mem::take(&mut *state.failed.lock().unwrap());
+        for (range, mut err) in failed {
+            let mut attempts = 1;
+            loop {
+                if attempts >= self.max_attempts {
+                    return Err(err);
+                }
+                attempts += 1;
+                match self.fetch_segment(&state, path, range.clone()).await {
+                    Ok(()) => break,
+                    Err(e) if e.has_error_code(ErrorCode::ResourceModified) => return Err(e),
+                    Err(e) => err = e,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn worker(&self, state: &SegmentState, path: &Path) -> Result<()> {
+        loop {
+            let Some(range) = state.queue.lock().unwrap().pop_front() else {
+                return Ok(());
+            };
+            if let Err(err) = self.fetch_segment(state, path, range.clone()).await {
+                if self.max_attempts <= 1 || err.has_error_code(ErrorCode::ResourceModified) {
+                    return Err(err);
+                }
+                state.failed.lock().unwrap().push((range, err));
+            }
+        }
+    }
+
+    /// Fetch a segment and write it into the file at `path`.
+    async fn fetch_segment(
+        &self,
+        state: &SegmentState,
+        path: &Path,
+        range: Range<u64>,
+    ) -> Result<()> {
+        let requested = ExpectRange::from(range.clone());
+        let mut refetched = false;
+        let resp = loop {
+            let (generation, url) = state.url.lock().await.clone();
+            let req = self
+                .onedrive
+                .client
+                .get(url)
+                .header(header::RANGE, range_header(requested));
+            let resp = send_pre_authenticated("SegmentedDownload::download_to_file", req).await?;
+            let expired = matches!(
+                resp.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::GONE
+            );
+            if !expired || refetched {
+                break handle_error_response(resp).await?;
+            }
+            // Re-fetch the expired download URL, unless another segment has done it.
+            let mut url = state.url.lock().await;
+            if url.0 == generation {
+                *url = (
+                    generation + 1,
+                    self.onedrive.get_item_download_url(self.item).await?,
+                );
+            }
+            refetched = true;
+        };
+
+        let diagnostics = ResponseDiagnostics::from_headers(resp.headers());
+        let download = RangedDownload::from_response(resp, requested)?;
+        if download.range() != range {
+            return Err(Error::unexpected_response(
+                "Segment is shorter than requested",
+            ));
+        }
+        if download
+            .total_size()
+            .is_some_and(|total| total != self.size)
+        {
+            return Err(Error::resource_modified(
+                "The file size differs from the expected one",
+                diagnostics,
+            ));
+        }
+        if let Some(tag) = download.e_tag() {
+            let mut e_tag = state.e_tag.lock().unwrap();
+            match &*e_tag {
+                Some(expect) if !same_e_tag(expect, tag) => {
+                    return Err(Error::resource_modified(
+                        "The file is modified during the download",
+                        diagnostics,
+                    ));
+                }
+                Some(_) => {}
+                None => *e_tag = Some(tag.clone()),
+            }
+        }
+
+        let mut file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+        file.seek(SeekFrom::Start(range.start)).await?;
+        download.write_to(&mut file).await?;
+        Ok(())
+    }
+}
+
+/// Write the body of `resp` into `writer`, and return the number of bytes written.
+pub(super) async fn write_response<W: AsyncWrite + Unpin + ?Sized>(
+    mut resp: Response,
+    writer: &mut W,
+) -> Result<u64> {
+    let mut len = 0u64;
+    while let Some(chunk) = resp.chunk().await? {
+        writer.write_all(&chunk).await?;
+        len += chunk.len() as u64;
+    }
+    writer.flush().await?;
+    Ok(len)
+}
+
+/// Write the body of `resp` into the temporary file of `path`, which is renamed to `path` on
+/// success and removed on failure. Return the number of bytes written.
+pub(super) async fn write_response_to_file(resp: Response, path: &Path) -> Result<u64> {
+    let tmp_path = part_path(path);
+    let ret = async {
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        let len = write_response(resp, &mut file).await?;
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&tmp_path, path).await?;
+        Ok(len)
+    }
+    .await;
+    if ret.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+    }
+    ret
+}
+
+/// Get the path of the temporary file for downloading into `path`, which is `<path>.part`.
+pub(super) fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Format a `Range` header value.
+///
+/// # Panics
+/// Panic if the range is empty.
+pub(super) fn range_header(range: ExpectRange) -> String {
+    match range.end {
+        None => format!("bytes={}-", range.start),
+        Some(end) => {
+            assert!(range.start < end, "Range must not be empty");
+            format!("bytes={}-{}", range.start, end - 1)
+        }
+    }
+}
+
+/// Parse `Content-Range` of a satisfied range, `bytes <first>-<last>/<total or *>`.
+/// Return the half-open range and the total size.
+fn parse_content_range(value: &str) -> Option<(Range<u64>, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    let (first, last) = (first.parse::<u64>().ok()?, last.parse::<u64>().ok()?);
+    let end = last.checked_add(1)?; // Exclusive.
+    if first > last {
+        return None;
+    }
+    let total = match total {
+        "*" => None,
+        s => Some(s.parse::<u64>().ok().filter(|&total| end <= total)?),
+    };
+    Some((first..end, total))
+}
+
+/// Check if two eTags are the same, ignoring the weakness and quotes.
+pub(super) fn same_e_tag(a: &Tag, b: &Tag) -> bool {
+    let normalize = |tag: &Tag| {
+        let s = tag.as_str();
+        s.strip_prefix("W/")
+            .unwrap_or(s)
+            .trim_matches('"')
+            .to_owned()
+    };
+    normalize(a) == normalize(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_range() {
+        assert_eq!(parse_content_range("bytes 0-0/1"), Some((0..1, Some(1))));
+        assert_eq!(parse_content_range("bytes 5-9/*"), Some((5..10, None)));
+        assert_eq!(parse_content_range("bytes 5-9/10"), Some((5..10, Some(10))));
+        assert_eq!(parse_content_range("bytes 5-9/9"), None);
+        assert_eq!(parse_content_range("bytes 9-5/10"), None);
+        assert_eq!(parse_content_range("bytes */10"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+
+        let range = |start, end| range_header(ExpectRange { start, end });
+        assert_eq!(range(0, None), "bytes=0-");
+        assert_eq!(range(5, Some(10)), "bytes=5-9");
+
+        let tag = |s: &str| Tag(s.to_owned());
+        assert!(same_e_tag(&tag("\"{ABC},2\""), &tag("{ABC},2")));
+        assert!(same_e_tag(&tag("W/\"abc\""), &tag("\"abc\"")));
+        assert!(!same_e_tag(&tag("\"{ABC},2\""), &tag("\"{ABC},3\"")));
+    }
+}
@@ -13,7 +13,7 @@
 use crate::{
     resource::{ResourceField, Tag},
     util::RequestBuilderTransformer,
-    ConflictBehavior,
+    ConflictBehavior, RetryPolicy,
 };
 use reqwest::{header, RequestBuilder};
 use std::{fmt::Write, marker::PhantomData};
@@ -22,6 +22,8 @@ use std::{fmt::Write, marker::PhantomData};
 struct AccessOption {
     if_match: Option<String>,
     if_none_match: Option<String>,
+    client_request_id: Option<String>,
+    retry_policy: Option<RetryPolicy>,
 }
 
 impl AccessOption {
@@ -34,6 +36,16 @@ impl AccessOption {
         self.if_none_match = Some(tag.0.clone());
         self
     }
+
+    fn client_request_id(mut self, id: String) -> Self {
+        self.client_request_id = Some(id);
+        self
+    }
+
+    fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
 }
 
 impl RequestBuilderTransformer for AccessOption {
@@ -44,8 +56,15 @@ impl RequestBuilderTransformer for AccessOption {
         if let Some(v) = self.if_none_match {
             req = req.header(header::IF_NONE_MATCH, v);
         }
+        if let Some(v) = self.client_request_id {
+            req = req.header("client-request-id", v);
+        }
         req
     }
+
+    fn get_retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
 }
 
 /// Option for GET-like requests to one resource object.
@@ -97,6 +116,38 @@ impl<Field: ResourceField> ObjectOption<Field> {
         self
     }
 
+    /// Set the `client-request-id` of the request, usually a GUID, to correlate it with logs
+    /// of the caller.
+    ///
+    /// It is echoed in the response, and is available in
+    /// [`Error::diagnostics`][diagnostics] on failure.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/best-practices-concept#reliability-and-support)
+    ///
+    /// [diagnostics]: ../struct.Error.html#method.diagnostics
+    #[must_use]
+    pub fn client_request_id(mut self, id: impl Into<String>) -> Self {
+        self.access_opt = self.access_opt.client_request_id(id.into());
+        self
+    }
+
+    /// Override the [`RetryPolicy`] of the [`OneDrive`][one_drive] instance for this call.
+    ///
+    /// Eg. use [`RetryPolicy::retry_non_idempotent`] for a request which is known to be safe to
+    /// replay, or `RetryPolicy::new().max_attempts(1)` to disable retrying.
+    ///
+    /// # Note
+    /// For calls returning a fetcher, this only applies to the first request. Later pages are
+    /// fetched under the policy of the `OneDrive` instance passed to the fetcher.
+    ///
+    /// [one_drive]: ../struct.OneDrive.html
+    #[must_use]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.access_opt = self.access_opt.retry_policy(retry_policy);
+        self
+    }
+
     /// Select only some fields of the resource object.
     ///
     /// See documentation of module [`onedrive_api::resource`][resource] for more details.
@@ -165,6 +216,10 @@ impl<Field: ResourceField> RequestBuilderTransformer for ObjectOption<Field> {
         }
         req
     }
+
+    fn get_retry_policy(&self) -> Option<&RetryPolicy> {
+        self.access_opt.get_retry_policy()
+    }
 }
 
 impl<Field: ResourceField> Default for ObjectOption<Field> {
@@ -218,6 +273,30 @@ impl<Field: ResourceField> CollectionOption<Field> {
         self
     }
 
+    /// Set the `client-request-id` of the request.
+    ///
+    /// # See also
+    /// [`ObjectOption::client_request_id`][client_request_id]
+    ///
+    /// [client_request_id]: ./struct.ObjectOption.html#method.client_request_id
+    #[must_use]
+    pub fn client_request_id(mut self, id: impl Into<String>) -> Self {
+        self.obj_option = self.obj_option.client_request_id(id);
+        self
+    }
+
+    /// Override the retry policy of the `OneDrive` instance for this call.
+    ///
+    /// # See also
+    /// [`ObjectOption::retry_policy`][retry_policy]
+    ///
+    /// [retry_policy]: ./struct.ObjectOption.html#method.retry_policy
+    #[must_use]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.obj_option = self.obj_option.retry_policy(retry_policy);
+        self
+    }
+
     /// Select only some fields of the resource object.
     ///
     /// See documentation of module [`onedrive_api::resource`][resource] for more details.
@@ -315,6 +394,10 @@ impl<Field: ResourceField> RequestBuilderTransformer for CollectionOption<Field>
         }
         req
     }
+
+    fn get_retry_policy(&self) -> Option<&RetryPolicy> {
+        self.obj_option.get_retry_policy()
+    }
 }
 
 impl<Field: ResourceField> Default for CollectionOption<Field> {
@@ -364,6 +447,30 @@ impl DriveItemPutOption {
 
     // `if_none_match` is not supported in PUT-like requests.
 
+    /// Set the `client-request-id` of the request.
+    ///
+    /// # See also
+    /// [`ObjectOption::client_request_id`][client_request_id]
+    ///
+    /// [client_request_id]: ./struct.ObjectOption.html#method.client_request_id
+    #[must_use]
+    pub fn client_request_id(mut self, id: impl Into<String>) -> Self {
+        self.access_opt = self.access_opt.client_request_id(id.into());
+        self
+    }
+
+    /// Override the retry policy of the `OneDrive` instance for this call.
+    ///
+    /// # See also
+    /// [`ObjectOption::retry_policy`][retry_policy]
+    ///
+    /// [retry_policy]: ./struct.ObjectOption.html#method.retry_policy
+    #[must_use]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.access_opt = self.access_opt.retry_policy(retry_policy);
+        self
+    }
+
     /// Specify the behavior if the target item already exists.
     ///
     /// # Note
@@ -386,6 +493,10 @@ impl RequestBuilderTransformer for DriveItemPutOption {
     fn trans(self, req: RequestBuilder) -> RequestBuilder {
         self.access_opt.trans(req)
     }
+
+    fn get_retry_policy(&self) -> Option<&RetryPolicy> {
+        self.access_opt.get_retry_policy()
+    }
 }
 
 #[cfg(test)]
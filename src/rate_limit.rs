@@ -0,0 +1,297 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use reqwest::{header::HeaderMap, Response, StatusCode};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A client-side limiter of requests, shared by all [`OneDrive`][one_drive] instances it is
+/// set to, including their clones.
+///
+/// It caps the number of in-flight requests, and optionally limits the request rate with a
+/// token bucket. It also slows down on its own under pressure:
+/// - On HTTP 429 or 503 responses, all requests are paused until `Retry-After` elapses, and the
+///   request rate is halved.
+/// - On `RateLimit-Remaining` and `RateLimit-Reset` headers, which are sent when the
+///   application is close to being throttled, the request rate is lowered to spread the
+///   remaining quota until reset.
+/// - On successful responses, the request rate recovers gradually to the configured one.
+///
+/// Cloning a `RateLimiter` shares the same limits.
+///
+/// A request occupies an in-flight slot until its response headers are received. Reading the
+/// response body, eg. of downloads, is not limited.
+///
+/// # Example
+/// ```
+/// use onedrive_api::{DriveLocation, OneDrive, RateLimiter};
+///
+/// let limiter = RateLimiter::new(8);
+/// limiter.set_requests_per_second(20.0);
+/// let drive = OneDrive::new("<...TOKEN...>", DriveLocation::me())
+///     .with_rate_limiter(limiter.clone());
+/// let other_drive = OneDrive::new("<...TOKEN...>", DriveLocation::me())
+///     .with_rate_limiter(limiter);
+/// ```
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/sharepoint/dev/general-development/how-to-avoid-getting-throttled-or-blocked-in-sharepoint-online#application-throttling)
+///
+/// [one_drive]: ./struct.OneDrive.html
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    max_concurrency: usize,
+    semaphore: Arc<Semaphore>,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+/// A permit to send a request, returned by [`RateLimiter::acquire`].
+///
+/// The in-flight slot is released when it is dropped.
+#[derive(Debug)]
+pub struct RateLimitPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing at most `max_concurrency` in-flight requests, without a
+    /// request rate limit.
+    ///
+    /// # Panics
+    /// Panic if `max_concurrency` is zero or too large.
+    #[must_use]
+    pub fn new(max_concurrency: usize) -> Self {
+        assert!(
+            0 < max_concurrency && max_concurrency <= Semaphore::MAX_PERMITS,
+            "Invalid max concurrency",
+        );
+        Self {
+            max_concurrency,
+            semaphore: Arc::new(Semaphore::new(max_concurrency)),
+            bucket: Arc::new(Mutex::new(Bucket::new(Instant::now()))),
+        }
+    }
+
+    /// Limit the request rate to at most `requests_per_second` on average.
+    ///
+    /// This affects all clones of this limiter, since they share the same limits.
+    ///
+    /// # Panics
+    /// Panic if `requests_per_second` is not positive and finite, or the internal lock is
+    /// poisoned.
+    pub fn set_requests_per_second(&self, requests_per_second: f64) {
+        assert!(
+            requests_per_second.is_finite() && requests_per_second > 0.0,
+            "Invalid requests per second",
+        );
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.max_rate = Some(requests_per_second);
+        bucket.rate = requests_per_second;
+    }
+
+    /// Set the maximum number of requests which can be sent at once without waiting for the
+    /// request rate limit, after a period of idleness.
+    ///
+    /// Default to be `1`. This affects all clones of this limiter, since they share the same
+    /// limits.
+    ///
+    /// # Panics
+    /// Panic if `burst` is zero, or the internal lock is poisoned.
+    pub fn set_burst(&self, burst: u32) {
+        assert_ne!(burst, 0, "Burst must not be zero");
+        self.bucket.lock().unwrap().burst = burst;
+    }
+
+    /// Get the maximum number of in-flight requests.
+    #[must_use]
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    /// Get the current request rate limit, which may be lower than the configured one under
+    /// pressure. Return `None` if the request rate is not limited.
+    ///
+    /// # Panics
+    /// Panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn current_requests_per_second(&self) -> Option<f64> {
+        let bucket = self.bucket.lock().unwrap();
+        bucket.max_rate.map(|_| bucket.rate)
+    }
+
+    /// Wait until a request can be sent.
+    ///
+    /// Requests of [`OneDrive`][one_drive] acquire permits automatically. This is for requests
+    /// sent by other means, eg. [`UploadSession::upload_part`][upload_part], to share the limits.
+    /// Use [`RateLimiter::report_throttled`] to report throttling of these requests.
+    ///
+    /// # Panics
+    /// Panic if the internal lock is poisoned.
+    ///
+    /// [one_drive]: ./struct.OneDrive.html
+    /// [upload_part]: ./struct.UploadSession.html#method.upload_part
+    pub async fn acquire(&self) -> RateLimitPermit {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Semaphore is never closed");
+        let delay = self.bucket.lock().unwrap().reserve(Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        RateLimitPermit { _permit: permit }
+    }
+
+    /// Report a throttled request with an optional delay hint from `Retry-After`, which is
+    /// available in [`Error::retry_after`][retry_after].
+    ///
+    /// This is done automatically for requests of [`OneDrive`][one_drive].
+    ///
+    /// # Panics
+    /// Panic if the internal lock is poisoned.
+    ///
+    /// [retry_after]: ./struct.Error.html#method.retry_after
+    /// [one_drive]: ./struct.OneDrive.html
+    pub fn report_throttled(&self, retry_after: Option<Duration>) {
+        self.bucket
+            .lock()
+            .unwrap()
+            .throttled(Instant::now(), retry_after);
+    }
+
+    /// Adjust limits by the response.
+    pub(crate) fn observe(&self, resp: &Response) {
+        let status = resp.status();
+        let now = Instant::now();
+        let mut bucket = self.bucket.lock().unwrap();
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = crate::util::parse_retry_after_sec(resp)
+                .map(|secs| Duration::from_secs(secs.into()));
+            #[cfg(feature = "tracing")]
+            tracing::info!(status = status.as_u16(), ?retry_after, "Throttled");
+            bucket.throttled(now, retry_after);
+        } else if let Some((remaining, reset)) = parse_rate_limit_headers(resp.headers()) {
+            bucket.under_pressure(now, remaining, reset);
+        } else if status.is_success() {
+            bucket.recover();
+        }
+    }
+}
+
+/// Parse `RateLimit-Remaining` and `RateLimit-Reset` (in seconds).
+fn parse_rate_limit_headers(headers: &HeaderMap) -> Option<(u32, Duration)> {
+    let get = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<u32>().ok();
+    let remaining = get("RateLimit-Remaining")?;
+    let reset = get("RateLimit-Reset")?;
+    Some((remaining, Duration::from_secs(reset.into())))
+}
+
+/// A token bucket implemented as GCRA (generic cell rate algorithm), with a pause for throttling.
+#[derive(Debug)]
+struct Bucket {
+    /// The configured rate, or `None` if not limited.
+    max_rate: Option<f64>,
+    /// The current rate, which is lowered under pressure.
+    rate: f64,
+    burst: u32,
+    /// The theoretical arrival time of the next request.
+    tat: Instant,
+    /// Requests are paused until this time.
+    paused_until: Instant,
+}
+
+impl Bucket {
+    /// The lower bound of the current rate, relative to the configured one.
+    const MIN_RATE_RATIO: f64 = 1.0 / 16.0;
+    /// The increase of the current rate on each success, relative to the configured one.
+    const RECOVER_RATIO: f64 = 1.0 / 32.0;
+
+    fn new(now: Instant) -> Self {
+        Self {
+            max_rate: None,
+            rate: f64::INFINITY,
+            burst: 1,
+            tat: now,
+            paused_until: now,
+        }
+    }
+
+    /// Reserve a slot for a request, and return the delay before sending it.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let start = self.paused_until.max(now);
+        if self.max_rate.is_some() {
+            let interval = Duration::from_secs_f64(1.0 / self.rate);
+            let tat = self.tat.max(start);
+            self.tat = tat + interval;
+            let allowed = tat
+                .checked_sub(interval * (self.burst - 1))
+                .map_or(start, |t| t.max(start));
+            return allowed.saturating_duration_since(now);
+        }
+        start.saturating_duration_since(now)
+    }
+
+    fn throttled(&mut self, now: Instant, retry_after: Option<Duration>) {
+        if let Some(delay) = retry_after {
+            self.paused_until = self.paused_until.max(now + delay);
+        }
+        if let Some(max_rate) = self.max_rate {
+            self.rate = (self.rate / 2.0).max(max_rate * Self::MIN_RATE_RATIO);
+        }
+    }
+
+    fn under_pressure(&mut self, now: Instant, remaining: u32, reset: Duration) {
+        if remaining == 0 {
+            self.paused_until = self.paused_until.max(now + reset);
+        }
+        if let Some(max_rate) = self.max_rate {
+            if !reset.is_zero() {
+                let spread = f64::from(remaining) / reset.as_secs_f64();
+                self.rate = self.rate.min(spread).max(max_rate * Self::MIN_RATE_RATIO);
+            }
+        }
+    }
+
+    fn recover(&mut self) {
+        if let Some(max_rate) = self.max_rate {
+            self.rate = (self.rate + max_rate * Self::RECOVER_RATIO).min(max_rate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket() {
+        let t0 = Instant::now();
+        let sec = Duration::from_secs(1);
+
+        let mut bucket = Bucket::new(t0);
+        assert_eq!(bucket.reserve(t0), Duration::ZERO);
+        bucket.throttled(t0, Some(sec * 2));
+        assert_eq!(bucket.reserve(t0 + sec), sec);
+        bucket.under_pressure(t0, 0, sec * 5);
+        assert_eq!(bucket.reserve(t0), sec * 5);
+
+        let mut bucket = Bucket::new(t0);
+        bucket.max_rate = Some(2.0);
+        bucket.rate = 2.0;
+        bucket.burst = 2;
+        let delays = (0..4).map(|_| bucket.reserve(t0)).collect::<Vec<_>>();
+        assert_eq!(delays, [Duration::ZERO, Duration::ZERO, sec / 2, sec]);
+
+        bucket.throttled(t0, None);
+        assert!((bucket.rate - 1.0).abs() < 1e-9);
+        bucket.under_pressure(t0, 1, sec * 10);
+        assert!((bucket.rate - 2.0 / 16.0).abs() < 1e-9);
+        for _ in 0..100 {
+            bucket.recover();
+        }
+        assert!((bucket.rate - 2.0).abs() < 1e-9);
+    }
+}
@@ -302,6 +302,107 @@ pub struct ErrorResponse {
     pub inner_error: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
+impl ErrorResponse {
+    /// Get the typed top-level `code`.
+    ///
+    /// Return [`ErrorCode::Unknown`] if it is not a documented code.
+    #[must_use]
+    pub fn error_code(&self) -> ErrorCode {
+        ErrorCode::parse(&self.code)
+    }
+
+    /// Get the top-level `code` followed by all nested `innererror.code`, from the most general
+    /// one to the most specific one.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/errors#code-property)
+    #[must_use]
+    pub fn code_chain(&self) -> Vec<&str> {
+        let mut codes = vec![&*self.code];
+        let mut inner = self.inner_error.as_ref();
+        while let Some(obj) = inner {
+            if let Some(code) = obj.get("code").and_then(|v| v.as_str()) {
+                codes.push(code);
+            }
+            inner = obj.get("innererror").and_then(|v| v.as_object());
+        }
+        codes
+    }
+
+    /// Check if `code` is the top-level `code` or any of nested `innererror.code`.
+    #[must_use]
+    pub fn has_error_code(&self, code: ErrorCode) -> bool {
+        self.code_chain()
+            .into_iter()
+            .any(|s| ErrorCode::parse(s) == code)
+    }
+}
+
+/// Documented error codes of [`ErrorResponse`].
+///
+/// Codes are matched case-insensitively.
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/onedrive/developer/rest-api/concepts/errors#error-resource-type)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::EnumString, strum::IntoStaticStr)]
+#[strum(serialize_all = "camelCase", ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// The caller doesn't have permission to perform the action.
+    AccessDenied,
+    /// The app or user has been throttled.
+    ActivityLimitReached,
+    /// The request requires an extension that failed.
+    ExtensionError,
+    /// An unspecified error has occurred.
+    GeneralException,
+    /// The access token is missing, invalid or expired.
+    #[strum(serialize = "InvalidAuthenticationToken")]
+    InvalidAuthenticationToken,
+    /// The specified byte range is invalid or unavailable.
+    InvalidRange,
+    /// The request is malformed or incorrect.
+    InvalidRequest,
+    /// The resource could not be found.
+    ItemNotFound,
+    /// Malware was detected in the requested resource.
+    MalwareDetected,
+    /// The specified item name already exists.
+    NameAlreadyExists,
+    /// The action is not allowed by the system.
+    NotAllowed,
+    /// The request is not supported by the system.
+    NotSupported,
+    /// The resource being updated has changed since the caller last read it, usually an eTag
+    /// mismatch.
+    ResourceModified,
+    /// The delta token is no longer valid, and the app must reset the sync state.
+    ResyncRequired,
+    /// The service is not available. Try the request again after a delay.
+    ServiceNotAvailable,
+    /// The sync state generation is not found. The delta token is expired and data must be
+    /// synchronized again.
+    SyncStateNotFound,
+    /// The user has reached their quota limit.
+    QuotaLimitReached,
+    /// The caller is not authenticated.
+    Unauthenticated,
+    /// Other codes which are not documented or not covered yet.
+    Unknown,
+}
+
+impl ErrorCode {
+    fn parse(code: &str) -> Self {
+        code.parse().unwrap_or(Self::Unknown)
+    }
+
+    /// Get the raw code string.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        self.into()
+    }
+}
+
 /// OAuth2 error response.
 ///
 /// **This struct is independent with [`ErrorResponse`][error_response] from storage API.**
@@ -327,6 +428,80 @@ pub struct OAuth2ErrorResponse {
     pub timestamp: Option<String>,
     pub trace_id: Option<String>,
     pub correlation_id: Option<String>,
+    /// The claims challenge to be passed to the next authorization request, eg. when
+    /// `error` is `interaction_required` due to Conditional Access policies.
+    pub claims: Option<String>,
+    /// More specific reason of `error`, eg. `consent_required` or `basic_action`.
+    pub suberror: Option<String>,
+}
+
+impl OAuth2ErrorResponse {
+    /// Classify the error into common cases, based on `error`, `suberror` and AADSTS
+    /// `error_codes`.
+    ///
+    /// # See also
+    /// [Microsoft Docs](https://learn.microsoft.com/en-us/entra/identity-platform/reference-error-codes)
+    #[must_use]
+    pub fn kind(&self) -> OAuth2ErrorKind {
+        // https://learn.microsoft.com/en-us/entra/identity-platform/reference-error-codes#aadsts-error-codes
+        const TENANT_MISMATCH_CODES: &[u32] = &[50020, 90072, 700_016];
+        const CONSENT_REQUIRED_CODES: &[u32] = &[65001];
+        const INTERACTION_REQUIRED_CODES: &[u32] = &[50076, 50079, 50158];
+
+        let has_code = |codes: &[u32]| {
+            self.error_codes
+                .iter()
+                .flatten()
+                .any(|code| codes.contains(code))
+        };
+        if has_code(TENANT_MISMATCH_CODES) {
+            return OAuth2ErrorKind::TenantMismatch;
+        }
+        if has_code(CONSENT_REQUIRED_CODES) || self.suberror.as_deref() == Some("consent_required")
+        {
+            return OAuth2ErrorKind::ConsentRequired;
+        }
+        if has_code(INTERACTION_REQUIRED_CODES) {
+            return OAuth2ErrorKind::InteractionRequired;
+        }
+        match &*self.error {
+            "invalid_grant" => OAuth2ErrorKind::InvalidGrant,
+            "consent_required" => OAuth2ErrorKind::ConsentRequired,
+            "interaction_required" | "login_required" => OAuth2ErrorKind::InteractionRequired,
+            "invalid_client" => OAuth2ErrorKind::InvalidClient,
+            "unauthorized_client" => OAuth2ErrorKind::UnauthorizedClient,
+            "access_denied" | "authorization_declined" => OAuth2ErrorKind::AccessDenied,
+            _ => OAuth2ErrorKind::Other,
+        }
+    }
+}
+
+/// Common cases of [`OAuth2ErrorResponse`], returned by [`OAuth2ErrorResponse::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OAuth2ErrorKind {
+    /// The grant is invalid, eg. the refresh token or code is expired, revoked or already
+    /// redeemed. The user should sign in again.
+    InvalidGrant,
+    /// The user or administrator has not consented to the requested scopes.
+    /// The user should sign in interactively to consent.
+    ConsentRequired,
+    /// The user needs to interact, eg. multi-factor authentication or Conditional Access
+    /// policies. See [`OAuth2ErrorResponse::claims`] for the claims challenge, if any.
+    InteractionRequired,
+    /// The client authentication failed, eg. the client secret is invalid or expired.
+    InvalidClient,
+    /// The client is not authorized to use this grant type, eg. public client flows are not
+    /// enabled in the app registration.
+    UnauthorizedClient,
+    /// The user or application does not exist in the tenant, eg. signing in with a personal
+    /// account to a work-or-school-only application (`AADSTS50020`), or the application is not
+    /// registered in the tenant (`AADSTS700016`).
+    TenantMismatch,
+    /// The user declined the authorization.
+    AccessDenied,
+    /// Other errors.
+    Other,
 }
 
 #[cfg(test)]
@@ -354,4 +529,98 @@ mod tests {
             "@microsoft.graph.downloadUrl",
         );
     }
+
+    #[test]
+    fn test_oauth2_error_kind() {
+        let resp = |error: &str, codes: &[u32], suberror: Option<&str>| OAuth2ErrorResponse {
+            error: error.to_owned(),
+            error_description: String::new(),
+            error_codes: Some(codes.to_vec()),
+            timestamp: None,
+            trace_id: None,
+            correlation_id: None,
+            claims: None,
+            suberror: suberror.map(Into::into),
+        };
+        let cases = [
+            (
+                resp("invalid_grant", &[70008], None),
+                OAuth2ErrorKind::InvalidGrant,
+            ),
+            (
+                resp("invalid_grant", &[65001], None),
+                OAuth2ErrorKind::ConsentRequired,
+            ),
+            (
+                resp("invalid_grant", &[50076], None),
+                OAuth2ErrorKind::InteractionRequired,
+            ),
+            (
+                resp("interaction_required", &[], Some("consent_required")),
+                OAuth2ErrorKind::ConsentRequired,
+            ),
+            (
+                resp("interaction_required", &[53003], None),
+                OAuth2ErrorKind::InteractionRequired,
+            ),
+            (
+                resp("invalid_request", &[50020], None),
+                OAuth2ErrorKind::TenantMismatch,
+            ),
+            (
+                resp("unauthorized_client", &[700_016], None),
+                OAuth2ErrorKind::TenantMismatch,
+            ),
+            (
+                resp("invalid_client", &[7_000_215], None),
+                OAuth2ErrorKind::InvalidClient,
+            ),
+            (
+                resp("unauthorized_client", &[], None),
+                OAuth2ErrorKind::UnauthorizedClient,
+            ),
+            (
+                resp("access_denied", &[], None),
+                OAuth2ErrorKind::AccessDenied,
+            ),
+            (
+                resp("temporarily_unavailable", &[], None),
+                OAuth2ErrorKind::Other,
+            ),
+        ];
+        for (resp, kind) in cases {
+            assert_eq!(resp.kind(), kind, "{resp:?}");
+        }
+    }
+
+    #[test]
+    fn test_error_code() {
+        let resp: ErrorResponse = serde_json::from_value(serde_json::json!({
+            "code": "invalidRequest",
+            "message": "Some message",
+            "innererror": {
+                "code": "invalidRange",
+                "innererror": {
+                    "code": "fragmentOverlap",
+                },
+            },
+        }))
+        .unwrap();
+        assert_eq!(resp.error_code(), ErrorCode::InvalidRequest);
+        assert_eq!(
+            resp.code_chain(),
+            ["invalidRequest", "invalidRange", "fragmentOverlap"],
+        );
+        assert!(resp.has_error_code(ErrorCode::InvalidRange));
+        assert!(resp.has_error_code(ErrorCode::Unknown));
+        assert!(!resp.has_error_code(ErrorCode::ItemNotFound));
+
+        assert_eq!(ErrorCode::parse("ItemNotFound"), ErrorCode::ItemNotFound);
+        assert_eq!(
+            ErrorCode::parse("InvalidAuthenticationToken"),
+            ErrorCode::InvalidAuthenticationToken,
+        );
+        assert_eq!(ErrorCode::parse("somethingElse"), ErrorCode::Unknown);
+        assert_eq!(ErrorCode::NameAlreadyExists.as_str(), "nameAlreadyExists");
+    }
 }
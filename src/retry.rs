@@ -0,0 +1,239 @@
+use std::{future::Future, time::Duration};
+
+use crate::{
+    error::{Error, Result},
+    util::parse_retry_after_sec,
+};
+use reqwest::{Method, RequestBuilder, Response};
+
+/// The policy to retry requests of [`OneDrive`][one_drive] on throttling, server unavailability
+/// and connection failures, with exponential backoff and jitter.
+///
+/// Requests are retried on connection failures, timeouts, and responses of HTTP 429, 502,
+/// 503, 504 and 509. Failures while reading the response body, after its headers are
+/// received, are not retried. The delay hint of the `Retry-After` header is respected if present,
+/// capped at `max_delay`. Otherwise, the n-th retry waits a random duration in `[b/2, b]`,
+/// where `b` is `initial_backoff * 2^(n-1)` capped at `max_backoff`.
+///
+/// Only idempotent requests (`GET`, `HEAD`, `PUT`, `DELETE` and `OPTIONS`) are retried by
+/// default, since a failed non-idempotent request may be already performed by the server.
+/// Requests with streaming body are never retried.
+///
+/// The policy applies to all calls of a [`OneDrive`][one_drive] instance, and can be
+/// overridden per call by the `retry_policy` of options, eg.
+/// [`DriveItemPutOption::retry_policy`][put_retry_policy].
+///
+/// # Example
+/// ```
+/// use onedrive_api::{
+///     option::DriveItemPutOption, DriveLocation, FileName, ItemLocation, OneDrive, RetryPolicy,
+/// };
+///
+/// # async fn run() -> onedrive_api::Result<()> {
+/// let policy = RetryPolicy::new().max_attempts(5);
+/// let drive = OneDrive::new("<...TOKEN...>", DriveLocation::me())
+///     .with_retry_policy(policy.clone());
+/// let item = drive.get_item(ItemLocation::root()).await?;
+///
+/// // Override for a call which is known to be safe to replay.
+/// drive
+///     .create_folder_with_option(
+///         ItemLocation::root(),
+///         FileName::new("dir").unwrap(),
+///         DriveItemPutOption::new().retry_policy(policy.retry_non_idempotent(true)),
+///     )
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/throttling)
+///
+/// [one_drive]: ./struct.OneDrive.html
+/// [put_retry_policy]: ./option/struct.DriveItemPutOption.html#method.retry_policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_delay: Duration,
+    retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// The default maximum number of attempts, including the first one.
+    pub const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+
+    /// The default backoff of the first retry.
+    pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+    /// The default maximum backoff of a retry, without `Retry-After`.
+    pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    /// The default maximum delay of a retry, including the one from `Retry-After`.
+    pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(300);
+
+    const RETRY_STATUS: &'static [u16] = &[429, 502, 503, 504, 509];
+
+    /// Create a policy with default parameters, which only retries idempotent requests.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            max_attempts: Self::DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: Self::DEFAULT_INITIAL_BACKOFF,
+            max_backoff: Self::DEFAULT_MAX_BACKOFF,
+            max_delay: Self::DEFAULT_MAX_DELAY,
+            retry_non_idempotent: false,
+        }
+    }
+
+    /// Set the maximum number of attempts, including the first one.
+    ///
+    /// Default to be [`RetryPolicy::DEFAULT_MAX_ATTEMPTS`]. `1` disables retrying.
+    ///
+    /// # Panics
+    /// Panic if `max_attempts` is zero.
+    #[must_use]
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        assert_ne!(max_attempts, 0, "Max attempts must not be zero");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the backoff of the first retry and the maximum backoff of any retry.
+    /// Delays from `Retry-After` are capped by [`max_delay`][max_delay] instead.
+    ///
+    /// Default to be [`RetryPolicy::DEFAULT_INITIAL_BACKOFF`] and
+    /// [`RetryPolicy::DEFAULT_MAX_BACKOFF`].
+    ///
+    /// [max_delay]: #method.max_delay
+    #[must_use]
+    pub fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Set the maximum delay of any retry, which caps the delay hint from `Retry-After`.
+    ///
+    /// Default to be [`RetryPolicy::DEFAULT_MAX_DELAY`].
+    #[must_use]
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set whether to retry non-idempotent requests, eg. `POST` and `PATCH`.
+    ///
+    /// Default to be `false`.
+    #[must_use]
+    pub fn retry_non_idempotent(mut self, retry_non_idempotent: bool) -> Self {
+        self.retry_non_idempotent = retry_non_idempotent;
+        self
+    }
+
+    /// Get the maximum number of attempts, including the first one.
+    pub(crate) fn get_max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Check if a failed request can be retried, eg. on throttling or connection failures.
+    pub(crate) fn is_retryable_error(err: &Error) -> bool {
+        err.is_connection_error()
+            || err
+                .status_code()
+                .is_some_and(|status| Self::RETRY_STATUS.contains(&status.as_u16()))
+    }
+
+    /// The delay before the `attempt`-th retry after `err`, which respects its `Retry-After`.
+    pub(crate) fn retry_delay(&self, attempt: u32, err: &Error) -> Duration {
+        err.retry_after()
+            .unwrap_or_else(|| self.backoff_delay(attempt))
+            .min(self.max_delay)
+    }
+
+    /// Send `req` by `send`, and retry on retryable failures.
+    pub(crate) async fn send<F, Fut>(&self, req: RequestBuilder, send: F) -> Result<Response>
+    where
+        F: Fn(RequestBuilder) -> Fut,
+        Fut: Future<Output = Result<Response>>,
+    {
+        if !self.is_retryable_request(&req) {
+            return send(req).await;
+        }
+
+        let mut req = req;
+        let mut attempt = 1;
+        loop {
+            let retry_req = req.try_clone().filter(|_| attempt < self.max_attempts);
+            let Some(retry_req) = retry_req else {
+                return send(req).await;
+            };
+            let delay = match send(req).await {
+                Ok(resp) if Self::RETRY_STATUS.contains(&resp.status().as_u16()) => {
+                    parse_retry_after_sec(&resp)
+                        .map_or_else(
+                            || self.backoff_delay(attempt),
+                            |secs| Duration::from_secs(secs.into()),
+                        )
+                        .min(self.max_delay)
+                }
+                Err(err) if err.is_connection_error() => self.backoff_delay(attempt),
+                ret => return ret,
+            };
+            #[cfg(feature = "tracing")]
+            crate::trace::record_retry(attempt, delay);
+            tokio::time::sleep(delay).await;
+            req = retry_req;
+            attempt += 1;
+        }
+    }
+
+    fn is_retryable_request(&self, req: &RequestBuilder) -> bool {
+        if self.max_attempts <= 1 {
+            return false;
+        }
+        // Streaming bodies cannot be cloned.
+        let Some(req) = req.try_clone().and_then(|req| req.build().ok()) else {
+            return false;
+        };
+        self.retry_non_idempotent
+            || matches!(
+                *req.method(),
+                Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+            )
+    }
+
+    /// The jittered delay before the `attempt`-th retry.
+    fn backoff_delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_backoff);
+        let half = backoff / 2;
+        let jitter = getrandom::u32().expect("Failed to generate random number");
+        half.mul_f64(1.0 + f64::from(jitter) / f64::from(u32::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_delay() {
+        let policy = RetryPolicy::new().backoff(Duration::from_secs(1), Duration::from_secs(5));
+        for (attempt, max_secs) in [(1, 1), (2, 2), (3, 4), (4, 5), (100, 5)] {
+            let max = Duration::from_secs(max_secs);
+            let delay = policy.backoff_delay(attempt);
+            assert!(max / 2 <= delay && delay <= max, "{attempt}: {delay:?}");
+        }
+    }
+}
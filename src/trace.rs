@@ -0,0 +1,137 @@
+//! Instrumentation of requests, with feature `tracing`.
+//!
+//! Access tokens, request bodies and query strings are never recorded. URLs outside of the API
+//! base URL, eg. pre-authenticated download and upload URLs, are reduced to their origins.
+use std::time::Duration;
+
+use crate::util::{ApiPathComponent, DriveLocation, ItemLocation};
+use reqwest::Response;
+use tracing::Span;
+use url::Url;
+
+/// Create the span of an operation, which is a `OneDrive` method or a request to a
+/// pre-authenticated URL if `drive` is `None`.
+///
+/// `item` is the item operated on, and `page` is the page index of fetchers, if any.
+pub(crate) fn span(
+    op: &'static str,
+    drive: Option<&DriveLocation>,
+    item: Option<&ItemLocation<'_>>,
+    page: Option<u32>,
+) -> Span {
+    let drive = drive.map(api_path);
+    let item = item.map(api_path);
+    tracing::info_span!("onedrive", op, drive, item, page, retries = 0u32)
+}
+
+/// The API path of a component, eg. `/me/drive` or `/items/<id>`.
+fn api_path(component: &(impl ApiPathComponent + ?Sized)) -> String {
+    let mut url = Url::parse("path:///").unwrap();
+    {
+        let mut segments = url.path_segments_mut().unwrap();
+        segments.pop_if_empty();
+        component.extend_into(&mut segments);
+    }
+    url.path().to_owned()
+}
+
+/// Record the result of sending a request.
+pub(crate) fn record_response(ret: &reqwest::Result<Response>, api_base: Option<&Url>) {
+    match ret {
+        Ok(resp) => {
+            let header = |name: &str| resp.headers().get(name).and_then(|v| v.to_str().ok());
+            tracing::debug!(
+                url = %redact_url(resp.url(), api_base),
+                status = resp.status().as_u16(),
+                request_id = header("request-id"),
+                client_request_id = header("client-request-id"),
+                "Received response",
+            );
+        }
+        Err(err) => {
+            // The `Display` of `reqwest::Error` contains the full URL.
+            let source = std::error::Error::source(err).map(ToString::to_string);
+            tracing::warn!(
+                url = err.url().map(|url| redact_url(url, api_base)),
+                timeout = err.is_timeout(),
+                connect = err.is_connect(),
+                error = source,
+                "Failed to send request",
+            );
+        }
+    }
+}
+
+/// Record a retry of the current operation.
+pub(crate) fn record_retry(retries: u32, delay: Duration) {
+    Span::current().record("retries", retries);
+    tracing::info!(retries, ?delay, "Retrying request");
+}
+
+/// Reduce `url` to its path relative to `api_base`, or its origin if it is outside of
+/// `api_base`.
+pub(crate) fn redact_url(url: &Url, api_base: Option<&Url>) -> String {
+    if let Some(base) = api_base {
+        let base = base.as_str().trim_end_matches('/');
+        if let Some(rest) = url.as_str().strip_prefix(base) {
+            if rest.is_empty() || rest.starts_with(['/', '?', '#']) {
+                let path = rest.split(['?', '#']).next().unwrap_or_default();
+                return path.to_owned();
+            }
+        }
+    }
+    format!("{}/<redacted>", url.origin().ascii_serialization())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn span_paths() {
+        assert_eq!(api_path(&DriveLocation::me()), "/me/drive");
+        assert_eq!(
+            api_path(&ItemLocation::from_path("/dir/a b").unwrap()),
+            "/root:%2Fdir%2Fa%20b:",
+        );
+        assert_eq!(api_path(&ItemLocation::root()), "/root");
+    }
+
+    #[test]
+    fn redact() {
+        let base = Url::parse("https://graph.microsoft.com/v1.0").unwrap();
+        let redact = |url: &str, base| redact_url(&Url::parse(url).unwrap(), base);
+        assert_eq!(
+            redact(
+                "https://graph.microsoft.com/v1.0/me/drive/root:%2Fa:?$select=id",
+                Some(&base),
+            ),
+            "/me/drive/root:%2Fa:",
+        );
+        assert_eq!(
+            redact(
+                "https://graph.microsoft.com/v1.0/me/drive/root/delta?token=abc",
+                Some(&base),
+            ),
+            "/me/drive/root/delta",
+        );
+        assert_eq!(
+            redact(
+                "https://graph.microsoft.com/v1.0evil/me/drive?access_token=abc",
+                Some(&base),
+            ),
+            "https://graph.microsoft.com/<redacted>",
+        );
+        assert_eq!(
+            redact(
+                "https://contoso-my.sharepoint.com/personal/_api/v2.0/drive/items/x/uploadSession?guid=1&tempauth=secret",
+                Some(&base),
+            ),
+            "https://contoso-my.sharepoint.com/<redacted>",
+        );
+        assert_eq!(
+            redact("https://public.dm.files.1drv.com/y4mSecret", None),
+            "https://public.dm.files.1drv.com/<redacted>",
+        );
+    }
+}
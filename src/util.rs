@@ -1,10 +1,11 @@
 use crate::{
-    error::{Error, Result},
+    error::{Error, ResponseDiagnostics, Result},
     resource::{DriveId, ErrorResponse, ItemId, OAuth2ErrorResponse},
+    retry::RetryPolicy,
 };
 use reqwest::{header, RequestBuilder, Response, StatusCode};
 use serde::{de, Deserialize};
-use url::PathSegmentsMut;
+use url::{PathSegmentsMut, Url};
 
 /// Specify the location of a `Drive` resource.
 ///
@@ -42,8 +43,14 @@ impl DriveLocation {
 
     /// OneDrive of a user.
     ///
+    /// This also works with app-only tokens from
+    /// [`Auth::login_with_client_credentials`][login_app], given `Files.Read.All` or
+    /// `Files.ReadWrite.All` application permission.
+    ///
     /// # See also
     /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/api/drive-get?view=graph-rest-1.0#get-a-users-onedrive)
+    ///
+    /// [login_app]: ./struct.Auth.html#method.login_with_client_credentials
     pub fn from_user(id_or_principal_name: impl Into<String>) -> Self {
         Self {
             inner: DriveLocationEnum::User(id_or_principal_name.into()),
@@ -62,8 +69,14 @@ impl DriveLocation {
 
     /// The document library for a site.
     ///
+    /// This also works with app-only tokens from
+    /// [`Auth::login_with_client_credentials`][login_app], given `Sites.Read.All` or
+    /// `Sites.ReadWrite.All` application permission.
+    ///
     /// # See also
     /// [Microsoft Docs](https://docs.microsoft.com/en-us/graph/api/drive-get?view=graph-rest-1.0#get-the-document-library-for-a-site)
+    ///
+    /// [login_app]: ./struct.Auth.html#method.login_with_client_credentials
     pub fn from_site(site_id: impl Into<String>) -> Self {
         Self {
             inner: DriveLocationEnum::Site(site_id.into()),
@@ -218,6 +231,153 @@ impl AsRef<str> for FileName {
     }
 }
 
+/// The cloud environment, which determines the hosts of the Microsoft identity platform (for
+/// [`Auth`][auth]) and Microsoft Graph (for [`OneDrive`][one_drive]).
+///
+/// The default is the global cloud. Presets are provided for national clouds, and other
+/// environments can be created by [`Cloud::new`].
+///
+/// # See also
+/// [Microsoft Docs](https://learn.microsoft.com/en-us/graph/deployments)
+///
+/// [auth]: ./struct.Auth.html
+/// [one_drive]: ./struct.OneDrive.html
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cloud {
+    authority_host: Url,
+    graph_host: Url,
+}
+
+impl Cloud {
+    /// Authority and Graph hosts of presets, in the order of constructors below.
+    const PRESETS: [(&'static str, &'static str); 5] = [
+        (
+            "https://login.microsoftonline.com",
+            "https://graph.microsoft.com",
+        ),
+        (
+            "https://login.microsoftonline.us",
+            "https://graph.microsoft.us",
+        ),
+        (
+            "https://login.microsoftonline.us",
+            "https://dod-graph.microsoft.us",
+        ),
+        (
+            "https://login.chinacloudapi.cn",
+            "https://microsoftgraph.chinacloudapi.cn",
+        ),
+        (
+            "https://login.microsoftonline.de",
+            "https://graph.microsoft.de",
+        ),
+    ];
+
+    fn preset(idx: usize) -> Self {
+        let (authority_host, graph_host) = Self::PRESETS[idx];
+        Self::new(
+            Url::parse(authority_host).unwrap(),
+            Url::parse(graph_host).unwrap(),
+        )
+    }
+
+    /// The global cloud. This is the default.
+    #[must_use]
+    pub fn global() -> Self {
+        Self::preset(0)
+    }
+
+    /// Microsoft Graph for US Government L4 (GCC High).
+    #[must_use]
+    pub fn us_gov() -> Self {
+        Self::preset(1)
+    }
+
+    /// Microsoft Graph for US Government L5 (DOD).
+    #[must_use]
+    pub fn us_gov_dod() -> Self {
+        Self::preset(2)
+    }
+
+    /// Microsoft Graph China operated by 21Vianet.
+    #[must_use]
+    pub fn china() -> Self {
+        Self::preset(3)
+    }
+
+    /// Microsoft Graph for Germany.
+    #[must_use]
+    pub fn germany() -> Self {
+        Self::preset(4)
+    }
+
+    /// Create a custom environment with the authority host, eg. `https://login.microsoftonline.com`,
+    /// and the Microsoft Graph host, eg. `https://graph.microsoft.com`.
+    #[must_use]
+    pub fn new(authority_host: Url, graph_host: Url) -> Self {
+        Self {
+            authority_host,
+            graph_host,
+        }
+    }
+
+    /// Get the authority host of the Microsoft identity platform.
+    #[must_use]
+    pub fn authority_host(&self) -> &Url {
+        &self.authority_host
+    }
+
+    /// Get the host of Microsoft Graph.
+    #[must_use]
+    pub fn graph_host(&self) -> &Url {
+        &self.graph_host
+    }
+
+    /// Get the URL of an endpoint under the authority host, with path segments appended.
+    pub(crate) fn authority_url<'a>(&self, segments: impl IntoIterator<Item = &'a str>) -> Url {
+        let mut url = self.authority_host.clone();
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    /// Get the base URL of Microsoft Graph v1.0 API.
+    pub(crate) fn graph_api_url(&self) -> Url {
+        let mut url = self.graph_host.clone();
+        url.path_segments_mut().unwrap().pop_if_empty().push("v1.0");
+        url
+    }
+
+    /// Get the `.default` scope of Microsoft Graph.
+    pub(crate) fn graph_default_scope(&self) -> String {
+        format!(
+            "{}/.default",
+            self.graph_host.as_str().trim_end_matches('/')
+        )
+    }
+
+    /// Strip the Microsoft Graph host of any preset from a resource-qualified scope.
+    pub(crate) fn strip_graph_resource(scope: &str) -> &str {
+        Self::PRESETS
+            .iter()
+            .find_map(|(_, graph_host)| {
+                let rest = scope.get(graph_host.len()..)?.strip_prefix('/')?;
+                scope[..graph_host.len()]
+                    .eq_ignore_ascii_case(graph_host)
+                    .then_some(rest)
+            })
+            .unwrap_or(scope)
+    }
+}
+
+impl Default for Cloud {
+    fn default() -> Self {
+        Self::global()
+    }
+}
+
 pub(crate) trait ApiPathComponent {
     fn extend_into(&self, buf: &mut PathSegmentsMut);
 }
@@ -256,6 +416,9 @@ impl ApiPathComponent for str {
 
 pub(crate) trait RequestBuilderTransformer {
     fn trans(self, req: RequestBuilder) -> RequestBuilder;
+
+    /// The retry policy overriding the one of `OneDrive` for this request, if any.
+    fn get_retry_policy(&self) -> Option<&RetryPolicy>;
 }
 
 pub(crate) trait RequestBuilderExt: Sized {
@@ -311,8 +474,20 @@ pub(crate) async fn handle_error_response(resp: Response) -> Result<Response> {
         Ok(resp)
     } else {
         let retry_after = parse_retry_after_sec(&resp);
-        let resp: Resp = resp.json().await?;
-        Err(Error::from_error_response(status, resp.error, retry_after))
+        let diagnostics = ResponseDiagnostics::from_headers(resp.headers());
+        let status_error = resp.error_for_status_ref().err();
+        let body = resp.bytes().await?;
+        match (serde_json::from_slice::<Resp>(&body), status_error) {
+            (Ok(resp), _) => Err(Error::from_error_response(
+                status,
+                resp.error,
+                retry_after,
+                diagnostics,
+            )),
+            // Error responses without a JSON body, eg. from pre-authorized download URLs.
+            (Err(_), Some(err)) => Err(Error::from_status_error(err, diagnostics)),
+            (Err(_), None) => Err(Error::unexpected_response("Invalid error response")),
+        }
     }
 }
 
@@ -331,7 +506,7 @@ pub(crate) async fn handle_oauth2_error_response(resp: Response) -> Result<Respo
 /// <https://learn.microsoft.com/en-us/graph/throttling#best-practices-to-handle-throttling>.
 /// And HTTP requires it to be a non-negative integer:
 /// <https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Retry-After>.
-fn parse_retry_after_sec(resp: &Response) -> Option<u32> {
+pub(crate) fn parse_retry_after_sec(resp: &Response) -> Option<u32> {
     resp.headers()
         .get(header::RETRY_AFTER)?
         .to_str()